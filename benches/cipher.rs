@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use secrecy::ExposeSecret;
+
+use horizon::cryptex::{encrypt_file_mode, encrypt_file_ref, BatchCipher, CipherMode, CipherTables, FormatVersion, XOR_NONCE_LENGTH};
+use horizon::{gene3, set_parallel_threshold};
+use horizon::keys::{RowKey, TableKey};
+
+const PAYLOAD_SIZE: usize = 1 << 20;
+
+/// Substitutes like `encrypt_file`, finding the position of each byte with `position`.
+fn substitute(input: &[u8], key1: &[u8], key2: &[u8], inverse: &[u8; 256], position: impl Fn(u8) -> usize) -> Vec<u8> {
+    input.iter().enumerate().map(|(i, &c)| {
+        let table_2d = key1[i % key1.len()] as usize;
+        let row = key2[i % key2.len()] as usize;
+        inverse[(table_2d + row + position(c)) % 256]
+    }).collect()
+}
+
+fn bench_char_positions(c: &mut Criterion) {
+    let key1 = TableKey::new(gene3(b"bench_key_one").unwrap());
+    let key2 = RowKey::new(gene3(b"bench_key_two").unwrap());
+    let payload: Vec<u8> = (0..PAYLOAD_SIZE).map(|i| (i * 31 % 251) as u8).collect();
+
+    let tables = CipherTables::from_seed(123456789);
+    let char_positions: HashMap<u8, usize> = tables.inverse.iter().enumerate().map(|(position, &c)| (c, position)).collect();
+
+    let (k1, k2) = (key1.expose_secret(), key2.expose_secret());
+    let with_map = substitute(&payload, k1, k2, &tables.inverse, |c| char_positions[&c]);
+    let with_array = substitute(&payload, k1, k2, &tables.inverse, |c| tables.forward[c as usize] as usize);
+    assert_eq!(with_map, with_array, "The array lookup changes the output");
+
+    let mut group = c.benchmark_group("char_positions");
+    group.throughput(Throughput::Bytes(PAYLOAD_SIZE as u64));
+    group.sample_size(20);
+    group.bench_function("hashmap", |b| {
+        b.iter(|| substitute(black_box(&payload), k1, k2, &tables.inverse, |c| char_positions[&c]))
+    });
+    group.bench_function("array", |b| {
+        b.iter(|| substitute(black_box(&payload), k1, k2, &tables.inverse, |c| tables.forward[c as usize] as usize))
+    });
+    group.bench_function("encrypt_file", |b| {
+        b.iter(|| encrypt_file_ref(black_box(&payload), &key1, &key2).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_cipher_modes(c: &mut Criterion) {
+    let key1 = TableKey::new(gene3(b"bench_key_one").unwrap());
+    let key2 = RowKey::new(gene3(b"bench_key_two").unwrap());
+    let payload: Vec<u8> = (0..PAYLOAD_SIZE).map(|i| (i * 31 % 251) as u8).collect();
+
+    let mut group = c.benchmark_group("cipher_mode");
+    group.throughput(Throughput::Bytes(PAYLOAD_SIZE as u64));
+    group.sample_size(20);
+    for (name, mode) in [("Table", CipherMode::Table), ("XorOnly", CipherMode::XorOnly { nonce: [0; XOR_NONCE_LENGTH] })] {
+        group.bench_function(name, |b| {
+            b.iter(|| encrypt_file_mode(black_box(&payload), &key1, &key2, mode).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Compares serial and parallel processing around `set_parallel_threshold`, without the key derivation of `encrypt_file`.
+fn bench_parallel_threshold(c: &mut Criterion) {
+    let key1 = TableKey::new(gene3(b"bench_key_one").unwrap());
+    let key2 = RowKey::new(gene3(b"bench_key_two").unwrap());
+    let cipher = BatchCipher::new(&key1, &key2, FormatVersion::V1).unwrap();
+
+    let mut group = c.benchmark_group("parallel_threshold");
+    group.sample_size(20);
+    for size in [256, 1 << 10, 4 << 10, 16 << 10, 64 << 10, 256 << 10, 1 << 20] {
+        let payload: Vec<u8> = (0..size).map(|i| (i * 31 % 251) as u8).collect();
+        group.throughput(Throughput::Bytes(size as u64));
+
+        for (name, threshold) in [("serial", usize::MAX), ("parallel", 0)] {
+            group.bench_function(format!("{} {} bytes", name, size), |b| {
+                set_parallel_threshold(threshold);
+                b.iter(|| cipher.encrypt(black_box(&payload)))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_char_positions, bench_cipher_modes, bench_parallel_threshold);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+//! Benchmarks of the internal primitives, through the unstable `__private` module as an external crate would.
+//!
+//! Run with `cargo bench --features bench-internals --bench internals`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use horizon::__private::{kdfwagen, seeded_shuffle, table3, unbiased_shuffle};
+use horizon::nebula::XofRng;
+
+fn bench_table3(c: &mut Criterion) {
+    let mut group = c.benchmark_group("table3");
+    group.sample_size(10);
+    for size in [16, 64, 256] {
+        group.bench_function(format!("{}", size), |b| b.iter(|| table3(black_box(size), 123456789)));
+    }
+    group.finish();
+}
+
+fn bench_kdfwagen(c: &mut Criterion) {
+    let mut group = c.benchmark_group("kdfwagen");
+    group.sample_size(20);
+    for iterations in [10, 100] {
+        group.bench_function(format!("{} iterations", iterations), |b| {
+            b.iter(|| kdfwagen(black_box(b"bench password"), b"bench salt", iterations).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_shuffles(c: &mut Criterion) {
+    let characters: Vec<u8> = (0..=255).collect();
+
+    let mut group = c.benchmark_group("shuffle");
+    group.bench_function("seeded_shuffle", |b| {
+        b.iter(|| {
+            let mut items = characters.clone();
+            seeded_shuffle(&mut items, black_box(123456789));
+            items
+        })
+    });
+    group.bench_function("unbiased_shuffle", |b| {
+        b.iter(|| {
+            let mut items = characters.clone();
+            unbiased_shuffle(&mut items, &mut XofRng::new(black_box(b"bench seed")));
+            items
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_table3, bench_kdfwagen, bench_shuffles);
+criterion_main!(benches);
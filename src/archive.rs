@@ -0,0 +1,365 @@
+use std::error::Error;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::cryptex::{decrypt_file, encrypt_file};
+use crate::format::{self, TRAILER_SIZE};
+use crate::keys::{KeyMaterial, RowKey, TableKey};
+use crate::systemtrayerror::SystemTrayError;
+
+/// Describes one encrypted blob stored in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Position of the encrypted blob from the start of the archive.
+    pub offset: u64,
+    /// Length in bytes of the encrypted blob.
+    pub length: u64,
+    /// Free-form metadata stored alongside the blob (file name, timestamp, ...).
+    pub metadata: Vec<u8>,
+}
+
+/// Writes several `encrypt_file` outputs one after the other, followed by an index table.
+///
+/// The archive layout is:
+///
+/// - the encrypted blobs, concatenated,
+/// - the index table: entry count (`u64`), then for each entry its offset (`u64`),
+///   length (`u64`), metadata length (`u32`) and metadata bytes,
+/// - the offset of the index table (`u64`).
+///
+/// All integers are stored in big-endian order, see the `format` module.
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    position: u64,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Creates a new `ArchiveWriter` writing into `writer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The destination of the archive.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use horizon::archive::ArchiveWriter;
+    ///
+    /// let writer = ArchiveWriter::new(File::create("backup.hzn").unwrap());
+    /// ```
+    pub fn new(writer: W) -> Self {
+        ArchiveWriter {
+            writer,
+            position: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Encrypts `plain_text` with `encrypt_file` and appends it to the archive.
+    ///
+    /// # Arguments
+    ///
+    /// * `plain_text` - The content to encrypt.
+    /// * `key1` - The first secret key given to `encrypt_file`.
+    /// * `key2` - The second secret key given to `encrypt_file`.
+    /// * `metadata` - Metadata stored unencrypted in the index table.
+    ///
+    /// # Returns
+    ///
+    /// The index of the new entry, or an error if encryption or writing fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::Cursor;
+    /// # use horizon::archive::ArchiveWriter;
+    /// # use horizon::gene3;
+    /// # use horizon::keys::{RowKey, TableKey};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let key1 = TableKey::new(gene3(b"archive key one")?);
+    /// # let key2 = RowKey::new(gene3(b"archive key two")?);
+    /// let mut archive = ArchiveWriter::new(Cursor::new(Vec::new()));
+    /// let index = archive.add_entry(b"content".to_vec(), &key1, &key2, b"file.txt")?;
+    /// assert_eq!(index, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_entry(&mut self, plain_text: Vec<u8>, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, metadata: &[u8]) -> Result<usize, Box<dyn Error>> {
+        let cipher_text = encrypt_file(plain_text, key1, key2)?;
+        self.writer.write_all(&cipher_text)?;
+
+        self.entries.push(ArchiveEntry {
+            offset: self.position,
+            length: cipher_text.len() as u64,
+            metadata: metadata.to_vec(),
+        });
+        self.position += cipher_text.len() as u64;
+
+        Ok(self.entries.len() - 1)
+    }
+
+    /// Writes the index table and the trailer, then returns the underlying writer.
+    ///
+    /// # Returns
+    ///
+    /// The underlying writer, or an error if writing fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::Cursor;
+    /// # use horizon::archive::ArchiveWriter;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let archive = ArchiveWriter::new(Cursor::new(Vec::new()));
+    /// let file = archive.finish()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn finish(mut self) -> Result<W, Box<dyn Error>> {
+        let index_offset = self.position;
+
+        let index = format::encode_index(&self.entries, index_offset);
+
+        self.writer.write_all(&index)?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+/// Reads an archive produced by `ArchiveWriter` and decrypts its entries on demand.
+pub struct ArchiveReader<R: Read + Seek> {
+    reader: R,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    /// Opens an archive by loading its index table.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The source of the archive.
+    ///
+    /// # Returns
+    ///
+    /// A new `ArchiveReader`, or an error if the index table is missing or malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use horizon::archive::ArchiveReader;
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let archive = ArchiveReader::new(File::open("backup.hzn")?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(mut reader: R) -> Result<Self, Box<dyn Error>> {
+        let archive_len = reader.seek(SeekFrom::End(0))?;
+        if archive_len < TRAILER_SIZE {
+            return Err(Box::new(SystemTrayError::new(11)));
+        }
+
+        reader.seek(SeekFrom::Start(archive_len - TRAILER_SIZE))?;
+        let mut trailer = [0u8; TRAILER_SIZE as usize];
+        reader.read_exact(&mut trailer)?;
+        let index_offset = u64::from_be_bytes(trailer);
+        if index_offset > archive_len - TRAILER_SIZE {
+            return Err(Box::new(SystemTrayError::new(11)));
+        }
+
+        reader.seek(SeekFrom::Start(index_offset))?;
+        let mut index = vec![0u8; (archive_len - TRAILER_SIZE - index_offset) as usize];
+        reader.read_exact(&mut index)?;
+
+        let entries = format::decode_index(&index, index_offset)?;
+
+        Ok(ArchiveReader { reader, entries })
+    }
+
+    /// Returns the entries listed in the index table.
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// Seeks to entry `i` and decrypts it, without reading the other entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `i` - The index of the entry to decrypt.
+    /// * `key1` - The first secret key given to `decrypt_file`.
+    /// * `key2` - The second secret key given to `decrypt_file`.
+    ///
+    /// # Returns
+    ///
+    /// The decrypted content of the entry, or an error if `i` is out of bounds or reading fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::Cursor;
+    /// # use horizon::archive::{ArchiveReader, ArchiveWriter};
+    /// # use horizon::gene3;
+    /// # use horizon::keys::{RowKey, TableKey};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let key1 = TableKey::new(gene3(b"archive key one")?);
+    /// # let key2 = RowKey::new(gene3(b"archive key two")?);
+    /// # let mut writer = ArchiveWriter::new(Cursor::new(Vec::new()));
+    /// # writer.add_entry(b"first".to_vec(), &key1, &key2, b"first.txt")?;
+    /// # writer.add_entry(b"content".to_vec(), &key1, &key2, b"second.txt")?;
+    /// # let mut archive = ArchiveReader::new(writer.finish()?)?;
+    /// let content = archive.entry(1, &key1, &key2)?;
+    /// assert_eq!(content, b"content");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn entry(&mut self, i: usize, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let entry = self.entries.get(i).ok_or_else(|| SystemTrayError::new(1))?;
+
+        let mut cipher_text = vec![0u8; entry.length as usize];
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        self.reader.read_exact(&mut cipher_text)?;
+
+        decrypt_file(cipher_text, key1, key2)
+    }
+
+    /// Decrypts every entry, going on after a failing one, and reports the outcome of each.
+    ///
+    /// A backup tool can then restore the entries that decrypted and list the others. The cipher text is not
+    /// authenticated: an entry encrypted with other keys decrypts to unrelated bytes without an error,
+    /// only the failures to read or decrypt an entry are reported as such.
+    ///
+    /// # Arguments
+    ///
+    /// * `key1` - The first secret key given to `decrypt_file`.
+    /// * `key2` - The second secret key given to `decrypt_file`.
+    ///
+    /// # Returns
+    ///
+    /// One `DecryptOutcome` per entry, in index order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::Cursor;
+    /// # use horizon::archive::{ArchiveReader, ArchiveWriter};
+    /// # use horizon::gene3;
+    /// # use horizon::keys::{RowKey, TableKey};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let key1 = TableKey::new(gene3(b"archive key one")?);
+    /// # let key2 = RowKey::new(gene3(b"archive key two")?);
+    /// # let mut writer = ArchiveWriter::new(Cursor::new(Vec::new()));
+    /// # writer.add_entry(b"first".to_vec(), &key1, &key2, b"first.txt")?;
+    /// # writer.add_entry(b"content".to_vec(), &key1, &key2, b"second.txt")?;
+    /// # let mut archive = ArchiveReader::new(writer.finish()?)?;
+    /// # fn restore(_entry: &horizon::archive::ArchiveEntry, _content: Vec<u8>) {}
+    /// for outcome in archive.decrypt_many(&key1, &key2) {
+    ///     match outcome.result {
+    ///         Ok(content) => restore(&archive.entries()[outcome.index], content),
+    ///         Err(err) => eprintln!("entry {} not restored: {}", outcome.index, err),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn decrypt_many(&mut self, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Vec<DecryptOutcome> {
+        (0..self.entries.len())
+            .map(|index| DecryptOutcome { index, result: self.entry(index, key1, key2) })
+            .collect()
+    }
+}
+
+/// The result of decrypting one entry of an archive with `ArchiveReader::decrypt_many`.
+#[derive(Debug)]
+pub struct DecryptOutcome {
+    /// The index of the entry.
+    pub index: usize,
+    /// The decrypted content, or the error that stopped this entry.
+    pub result: Result<Vec<u8>, Box<dyn Error>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::gene3;
+
+    use super::*;
+
+    #[test]
+    fn test_archive_random_access() {
+        let key1 = TableKey::new(gene3(b"archive_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"archive_key_two").unwrap());
+        let contents: [&[u8]; 3] = [b"first entry", b"second entry with \0 bytes", b"third"];
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new()));
+        for (i, content) in contents.iter().enumerate() {
+            let index = writer.add_entry(content.to_vec(), &key1, &key2, format!("entry{}", i).as_bytes()).unwrap();
+            assert_eq!(index, i);
+        }
+        let archive = writer.finish().unwrap().into_inner();
+
+        let mut reader = ArchiveReader::new(Cursor::new(archive)).unwrap();
+        assert_eq!(reader.entries().len(), 3);
+        assert_eq!(reader.entries()[2].metadata, b"entry2");
+
+        assert_eq!(reader.entry(1, &key1, &key2).unwrap(), contents[1]);
+        assert_eq!(reader.entry(0, &key1, &key2).unwrap(), contents[0]);
+        assert!(reader.entry(3, &key1, &key2).is_err());
+    }
+
+    /// A reader failing on every read that starts inside `failing`, like a damaged sector.
+    struct DamagedReader {
+        inner: Cursor<Vec<u8>>,
+        failing: std::ops::Range<u64>,
+    }
+
+    impl Read for DamagedReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.failing.contains(&self.inner.position()) {
+                return Err(std::io::Error::other("damaged sector"));
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for DamagedReader {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_decrypt_many_outcomes() {
+        let key1 = TableKey::new(gene3(b"archive_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"archive_key_two").unwrap());
+        let other_key2 = RowKey::new(gene3(b"archive_key_other").unwrap());
+        let contents: [&[u8]; 4] = [b"first entry", b"unreadable entry", b"under other keys", b"last entry"];
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new()));
+        for (i, content) in contents.iter().enumerate() {
+            let row_key = if i == 2 { &other_key2 } else { &key2 };
+            writer.add_entry(content.to_vec(), &key1, row_key, b"").unwrap();
+        }
+        let archive = writer.finish().unwrap().into_inner();
+
+        let unreadable = ArchiveReader::new(Cursor::new(archive.clone())).unwrap().entries()[1].offset;
+        let mut reader = ArchiveReader::new(DamagedReader { inner: Cursor::new(archive), failing: unreadable..unreadable + 1 }).unwrap();
+
+        let outcomes = reader.decrypt_many(&key1, &key2);
+        assert_eq!(outcomes.iter().map(|outcome| outcome.index).collect::<Vec<usize>>(), [0, 1, 2, 3]);
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), contents[0]);
+        assert!(outcomes[1].result.is_err());
+        // Not authenticated: the wrong key gives unrelated bytes, not an error
+        assert_ne!(outcomes[2].result.as_ref().unwrap(), contents[2]);
+        assert_eq!(outcomes[3].result.as_ref().unwrap(), contents[3]);
+    }
+
+    #[test]
+    fn test_archive_truncated() {
+        assert!(ArchiveReader::new(Cursor::new(vec![0u8; 4])).is_err());
+        assert!(ArchiveReader::new(Cursor::new(vec![0xFF; 16])).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use std::error::Error;
+
+use secrecy::{Secret, Zeroize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::cryptex::{decrypt_file, encrypt_file};
+use crate::keys::{KeyMaterial, RowKey, TableKey};
+use crate::systemtrayerror::SystemTrayError;
+
+/// Encrypts everything read from `reader` with `encrypt_file` and writes the result to `writer`.
+///
+/// The IO is asynchronous, while the CPU-bound encryption runs on tokio's blocking thread pool
+/// so it does not stall the other tasks of the runtime.
+///
+/// # Arguments
+///
+/// * `reader` - The source of the content to be encrypted.
+/// * `writer` - The destination of the encrypted content.
+/// * `key1` - A secret key used for encryption.
+/// * `key2` - Another secret key used for encryption.
+///
+/// # Returns
+///
+/// The number of encrypted bytes written, or an error if reading, encryption or writing fails.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::async_io::encrypt_file_async;
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// # let key1 = TableKey::new(gene3(b"upload key one")?);
+/// # let key2 = RowKey::new(gene3(b"upload key two")?);
+/// let upload: &[u8] = b"uploaded bytes";
+/// let mut stored = Vec::new();
+/// let written = encrypt_file_async(upload, &mut stored, &key1, &key2).await?;
+/// assert_eq!(written, stored.len() as u64);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn encrypt_file_async<R, W>(mut reader: R, mut writer: W, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<u64, Box<dyn Error + Send + Sync>>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut plain_text = Vec::new();
+    reader.read_to_end(&mut plain_text).await?;
+
+    let (key1, key2) = owned_keys(key1, key2);
+    let cipher_text = tokio::task::spawn_blocking(move || {
+        encrypt_file(plain_text, &key1, &key2).map_err(sendable)
+    }).await??;
+
+    writer.write_all(&cipher_text).await?;
+    writer.flush().await?;
+
+    Ok(cipher_text.len() as u64)
+}
+
+/// Decrypts everything read from `reader` with `decrypt_file` and writes the result to `writer`.
+///
+/// # Arguments
+///
+/// * `reader` - The source of the encrypted content.
+/// * `writer` - The destination of the decrypted content.
+/// * `key1` - A secret key used for decryption.
+/// * `key2` - Another secret key used for decryption.
+///
+/// # Returns
+///
+/// The number of decrypted bytes written, or an error if reading, decryption or writing fails.
+/// The decrypted content is zeroized once written, or when writing fails.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::async_io::{decrypt_file_async, encrypt_file_async};
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// # let key1 = TableKey::new(gene3(b"upload key one")?);
+/// # let key2 = RowKey::new(gene3(b"upload key two")?);
+/// # let mut stored = Vec::new();
+/// # encrypt_file_async(&b"uploaded bytes"[..], &mut stored, &key1, &key2).await?;
+/// let mut response_body = Vec::new();
+/// decrypt_file_async(&stored[..], &mut response_body, &key1, &key2).await?;
+/// assert_eq!(response_body, b"uploaded bytes");
+/// # Ok(())
+/// # }
+/// ```
+pub async fn decrypt_file_async<R, W>(mut reader: R, mut writer: W, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<u64, Box<dyn Error + Send + Sync>>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut cipher_text = Vec::new();
+    reader.read_to_end(&mut cipher_text).await?;
+
+    let (key1, key2) = owned_keys(key1, key2);
+    let mut plain_text = tokio::task::spawn_blocking(move || {
+        decrypt_file(cipher_text, &key1, &key2).map_err(sendable)
+    }).await??;
+
+    let written = async {
+        writer.write_all(&plain_text).await?;
+        writer.flush().await
+    }.await;
+    let length = plain_text.len() as u64;
+    plain_text.zeroize();
+    written?;
+
+    Ok(length)
+}
+
+/// Makes an error of the cipher sendable back from the blocking thread pool, keeping a `SystemTrayError`
+/// downcastable to read its code.
+fn sendable(err: Box<dyn Error>) -> Box<dyn Error + Send + Sync> {
+    match err.downcast::<SystemTrayError>() {
+        Ok(err) => err,
+        Err(err) => err.to_string().into(),
+    }
+}
+
+/// Copies the keys so they can be moved to the blocking thread pool.
+fn owned_keys(key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> (TableKey, RowKey) {
+    (
+        TableKey::new(Secret::new(key1.expose().to_vec())),
+        RowKey::new(Secret::new(key2.expose().to_vec())),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gene3;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_async_round_trip() {
+        let plain_text = b"uploaded file encrypted without blocking the runtime".repeat(100);
+        let key1 = TableKey::new(gene3(b"async_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"async_key_two").unwrap());
+
+        let mut cipher_text = Vec::new();
+        let written = encrypt_file_async(plain_text.as_slice(), &mut cipher_text, &key1, &key2).await.unwrap();
+        assert_eq!(written, cipher_text.len() as u64);
+
+        let mut decrypted = Vec::new();
+        decrypt_file_async(cipher_text.as_slice(), &mut decrypted, &key1, &key2).await.unwrap();
+        assert_eq!(decrypted, plain_text);
+    }
+
+    #[tokio::test]
+    async fn test_async_error_code() {
+        let key1 = TableKey::new(Secret::new(vec![1; 10]));
+        let key2 = RowKey::new(Secret::new(vec![2; 10]));
+
+        let error = decrypt_file_async(b"cipher text".as_slice(), Vec::new(), &key1, &key2).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<SystemTrayError>().unwrap().code, 5);
+    }
+}
@@ -1,10 +1,159 @@
 use std::error::Error;
+use std::io::{self, Read};
 
-use hashbrown::HashMap;
 use rayon::prelude::*;
-use secrecy::{ExposeSecret, Secret};
+use secrecy::{ExposeSecret, Secret, Zeroize};
 
-use crate::{addition_chiffres, KEY_LENGTH, nebula, shift_bits, table3, unshift_bits, vz_maker, xor_crypt3};
+use crate::format::{self, ALIGNED_LENGTH_SIZE};
+use crate::keys::{KeyMaterial, RowKey, TableKey};
+use crate::nebula::{Nebula, secured_seed};
+use crate::systemtrayerror::SystemTrayError;
+use crate::{addition_chiffres, constant_time_eq, KEY_LENGTH, keyed_row, map_indexed, nebula, seed_v2, shift_bits, unshift_bits, vz_maker, xor_crypt3};
+
+/// The versions of the encryption scheme.
+///
+/// The cipher text does not record its version, the same one has to be given for decryption.
+/// Versions are ordered from the oldest to the newest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FormatVersion {
+    /// The table seed is the product of the byte sums of the keys, see `derive_seed`.
+    /// Keys with the same byte sums, such as the same bytes in another order or swapped keys, give the same tables.
+    #[default]
+    V1,
+    /// The table seed is a BLAKE3 hash of both keys, see `derive_seed_v2`, so it depends on their order and on every byte.
+    V2,
+    /// The table seed of `V2`, with every row an independent keyed permutation as built by `table3_keyed`
+    /// instead of a rotation of the same shuffled alphabet.
+    V3,
+}
+
+impl FormatVersion {
+    /// Computes the seed of the cipher tables for this version.
+    pub(crate) fn table_seed(self, key1: &[u8], key2: &[u8]) -> u64 {
+        match self {
+            FormatVersion::V1 => addition_chiffres(key1).wrapping_mul(addition_chiffres(key2)),
+            FormatVersion::V2 | FormatVersion::V3 => (seed_v2(key1, key2) >> 64) as u64,
+        }
+    }
+}
+
+/// Length in bytes of the nonce of `CipherMode::XorOnly`.
+pub const XOR_NONCE_LENGTH: usize = 16;
+
+/// The transforms applied by `encrypt_file_mode`.
+///
+/// As with `FormatVersion`, the cipher text does not record its mode: the same one, nonce included,
+/// has to be given for decryption.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CipherMode {
+    /// The full cipher of `encrypt_file`: substitution, XOR and bit shift.
+    #[default]
+    Table,
+    /// Only a XOR with a BLAKE3 keystream derived from both keys and `nonce`, for low-sensitivity data such as caches.
+    ///
+    /// It is much faster, but there is no authentication. The nonce has to be new for every message encrypted under
+    /// the same keys, for example `secured_seed()?.to_be_bytes()`, and stored next to the cipher text: two messages
+    /// sharing keys and nonce share their keystream, and XORing their cipher texts gives the XOR of their plain texts.
+    XorOnly {
+        /// The per-message nonce mixed into the keystream.
+        nonce: [u8; XOR_NONCE_LENGTH],
+    },
+}
+
+/// The substitution tables of the cipher, derived from the key seed.
+///
+/// The 3D table built by `table3` has `table[t][r][k] == inverse[(t + r + k) % 256]`, so both directions
+/// are computed from these two arrays: a lookup costs the same for every byte, with no hashing and no linear scan.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CipherTables {
+    /// Position of each byte in the shuffled alphabet.
+    pub forward: [u8; 256],
+    /// Byte at each position of the shuffled alphabet, the inverse of `forward`.
+    pub inverse: [u8; 256],
+}
+
+impl CipherTables {
+    /// Builds the tables for `seed`, shuffling the alphabet like `table3` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed derived from the keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use horizon::cryptex::CipherTables;
+    /// # let seed = 42;
+    /// let tables = CipherTables::from_seed(seed);
+    /// assert_eq!(tables.inverse[tables.forward[42] as usize], 42);
+    /// ```
+    pub fn from_seed(seed: u64) -> Self {
+        let mut characters: Vec<u8> = (0..=255).collect();
+        nebula::seeded_shuffle(&mut characters, seed as usize);
+
+        let mut forward = [0u8; 256];
+        let mut inverse = [0u8; 256];
+        for (position, &c) in characters.iter().enumerate() {
+            forward[c as usize] = position as u8;
+            inverse[position] = c;
+        }
+
+        CipherTables { forward, inverse }
+    }
+
+    /// Substitutes `byte` with the entry of the 2D table `table_2d` at row `row`.
+    pub(crate) fn encrypt_byte(&self, table_2d: usize, row: usize, byte: u8) -> u8 {
+        let col = self.forward[byte as usize] as usize;
+        self.inverse[(table_2d + row + col) % 256]
+    }
+
+    /// Reverses `encrypt_byte`.
+    pub(crate) fn decrypt_byte(&self, table_2d: usize, row: usize, byte: u8) -> u8 {
+        let col = (self.forward[byte as usize] as usize + 512 - table_2d - row) % 256;
+        self.inverse[col]
+    }
+}
+
+/// The substitution applied to each position of the text, before the XOR and bit shift stages.
+enum Substitutions {
+    /// The rows are rotations of the shuffled alphabet of the tables.
+    Rotated(Box<CipherTables>),
+    /// One keyed row per key position, with its inverse.
+    Keyed(Vec<([u8; 256], [u8; 256])>),
+}
+
+impl Substitutions {
+    /// Builds the substitution of `version` for the key bytes of each position.
+    fn new(version: FormatVersion, seed: u64, key1_chars: &[usize], key2_chars: &[usize]) -> Self {
+        if version != FormatVersion::V3 {
+            return Substitutions::Rotated(Box::new(CipherTables::from_seed(seed)));
+        }
+
+        Substitutions::Keyed((0..KEY_LENGTH).into_par_iter().map(|i| {
+            let mut forward = [0u8; 256];
+            let mut inverse = [0u8; 256];
+            for (c, &substitute) in keyed_row(seed, key1_chars[i], key2_chars[i], 256).iter().enumerate() {
+                forward[c] = substitute;
+                inverse[substitute as usize] = c as u8;
+            }
+            (forward, inverse)
+        }).collect())
+    }
+
+    fn encrypt_byte(&self, i: usize, table_2d: usize, row: usize, byte: u8) -> u8 {
+        match self {
+            Substitutions::Rotated(tables) => tables.encrypt_byte(table_2d, row, byte),
+            Substitutions::Keyed(rows) => rows[i % KEY_LENGTH].0[byte as usize],
+        }
+    }
+
+    fn decrypt_byte(&self, i: usize, table_2d: usize, row: usize, byte: u8) -> u8 {
+        match self {
+            Substitutions::Rotated(tables) => tables.decrypt_byte(table_2d, row, byte),
+            Substitutions::Keyed(rows) => rows[i % KEY_LENGTH].1[byte as usize],
+        }
+    }
+}
 
 /// This function encrypts the content of a file using two secret keys and a password.
 ///
@@ -25,19 +174,21 @@ use crate::{addition_chiffres, KEY_LENGTH, nebula, shift_bits, table3, unshift_b
 ///
 /// # Example
 ///
-/// ```
-/// use your_crate::encrypt_file;
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use horizon::cryptex::encrypt_file;
+/// use horizon::gene3;
+/// use horizon::keys::{RowKey, TableKey};
 ///
 /// // Read the content of the file to be encrypted
 /// let plain_text = std::fs::read("file.txt").expect("Failed to read file");
 ///
-/// // Provide secret keys and a password
-/// let key1 = Secret::new(vec![1, 2, 3]);
-/// let key2 = Secret::new(vec![4, 5, 6]);
-/// let password = "my_password";
+/// // Derive the secret keys from passwords
+/// let key1 = TableKey::new(gene3(b"first password")?);
+/// let key2 = RowKey::new(gene3(b"second password")?);
 ///
 /// // Encrypt the content of the file
-/// let encrypted = encrypt_file(plain_text, &key1, &key2, password);
+/// let encrypted = encrypt_file(plain_text, &key1, &key2);
 ///
 /// match encrypted {
 ///     Ok(encrypted_content) => {
@@ -49,56 +200,233 @@ use crate::{addition_chiffres, KEY_LENGTH, nebula, shift_bits, table3, unshift_b
 ///         eprintln!("Error occurred during encryption: {:?}", err);
 ///     }
 /// }
+/// # Ok(())
+/// # }
 /// ```
-pub(crate) fn encrypt_file(plain_text: Vec<u8>, key1: &Secret<Vec<u8>>, key2: &Secret<Vec<u8>>) -> Result<Vec<u8>, Box<dyn Error>> {
+pub fn encrypt_file(mut plain_text: Vec<u8>, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let cipher_text = encrypt_file_ref(&plain_text, key1, key2);
 
-    let key1 = key1.expose_secret();
-    let key2 = key2.expose_secret();
+    // The plain text is owned here, wipe it so it does not linger in freed memory
+    plain_text.zeroize();
 
-    let val1 = addition_chiffres(key2);
-    let val2 = addition_chiffres(key1);
+    cipher_text
+}
+
+/// Encrypts borrowed content with the same scheme as `encrypt_file`.
+///
+/// The caller keeps ownership of `plain_text`, and is responsible for wiping it if needed.
+///
+/// # Arguments
+///
+/// * `plain_text` - The content to be encrypted.
+/// * `key1` - A secret key used for encryption.
+/// * `key2` - Another secret key used for encryption.
+///
+/// # Returns
+///
+/// A `Result` containing the encrypted content, or an error if encryption fails.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::encrypt_file_ref;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let plain_text = b"secret content".to_vec();
+/// let encrypted = encrypt_file_ref(&plain_text, &key1, &key2)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt_file_ref(plain_text: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Vec<u8>, Box<dyn Error>> {
+    encrypt_file_versioned(plain_text, key1, key2, FormatVersion::V1)
+}
+
+/// Encrypts borrowed content with the given `CipherMode`.
+///
+/// # Arguments
+///
+/// * `plain_text` - The content to be encrypted.
+/// * `key1` - A secret key used for encryption.
+/// * `key2` - Another secret key used for encryption.
+/// * `mode` - The mode, which has to be given again to `decrypt_file_mode`.
+///
+/// # Returns
+///
+/// A `Result` containing the encrypted content, or an error if encryption fails.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::{encrypt_file_mode, CipherMode};
+/// # use horizon::nebula::secured_seed;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let cache_entry = b"cached response".to_vec();
+/// let nonce = secured_seed()?.to_be_bytes();
+/// let obfuscated = encrypt_file_mode(&cache_entry, &key1, &key2, CipherMode::XorOnly { nonce })?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt_file_mode(plain_text: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, mode: CipherMode) -> Result<Vec<u8>, Box<dyn Error>> {
+    match mode {
+        CipherMode::Table => encrypt_file_ref(plain_text, key1, key2),
+        CipherMode::XorOnly { nonce } => xor_keystream(plain_text, key1, key2, &nonce),
+    }
+}
+
+/// Decrypts content produced by `encrypt_file_mode` with the same mode.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The content to be decrypted.
+/// * `key1` - A secret key used for decryption.
+/// * `key2` - Another secret key used for decryption.
+/// * `mode` - The mode the content was encrypted with.
+///
+/// # Returns
+///
+/// A `Result` containing the decrypted content, or an error if decryption fails.
+pub fn decrypt_file_mode(cipher_text: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, mode: CipherMode) -> Result<Vec<u8>, Box<dyn Error>> {
+    match mode {
+        CipherMode::Table => decrypt_file_ref(cipher_text, key1, key2),
+        // XOR is its own inverse
+        CipherMode::XorOnly { nonce } => xor_keystream(cipher_text, key1, key2, &nonce),
+    }
+}
+
+/// XORs `input` with the keystream of `CipherMode::XorOnly` for `nonce`.
+fn xor_keystream(input: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, nonce: &[u8; XOR_NONCE_LENGTH]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key1 = key1.expose();
+    let key2 = key2.expose();
+    check_keys(key1, key2)?;
+
+    let mut hasher = blake3::Hasher::new_derive_key("horizon xor only keystream");
+    hasher.update(&(key1.len() as u64).to_be_bytes());
+    hasher.update(key1);
+    hasher.update(key2);
+    // Fixed length and last, so the key bytes cannot be shifted into it
+    hasher.update(nonce);
+
+    let mut output = vec![0u8; input.len()];
+    hasher.finalize_xof().fill(&mut output);
+    output.iter_mut().zip(input).for_each(|(byte, &c)| *byte ^= c);
+
+    Ok(output)
+}
+
+/// Encrypts borrowed content like `encrypt_file_ref`, then checks that the output decrypts back to it.
+///
+/// This is a safety net for critical data: a bug making the cipher irreversible, such as one dropping
+/// some bytes, is reported at encryption time instead of when the data is needed. It doubles the cost of encryption.
+///
+/// # Arguments
+///
+/// * `plain_text` - The content to be encrypted.
+/// * `key1` - A secret key used for encryption.
+/// * `key2` - Another secret key used for encryption.
+///
+/// # Returns
+///
+/// A `Result` containing the encrypted content, or an error if encryption fails.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 22 if the cipher text does not decrypt to `plain_text`.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::encrypt_verified;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let plain_text = b"secret content".to_vec();
+/// let encrypted = encrypt_verified(&plain_text, &key1, &key2)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt_verified(plain_text: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let cipher_text = encrypt_file_ref(plain_text, key1, key2)?;
+    check_round_trip(plain_text, &cipher_text, |cipher_text| decrypt_file_ref(cipher_text, key1, key2))?;
+
+    Ok(cipher_text)
+}
 
-    let mut characters: Vec<u8> = (0..=255).collect();
-    let seed= val2 * val1;
-    let table = table3(256, seed);
+/// Decrypts `cipher_text` with `decrypt` and compares the result to `plain_text` in constant time, returning code 22 if they differ.
+fn check_round_trip(plain_text: &[u8], cipher_text: &[u8], decrypt: impl Fn(&[u8]) -> Result<Vec<u8>, Box<dyn Error>>) -> Result<(), SystemTrayError> {
+    let mut decrypted = decrypt(cipher_text).map_err(|_| SystemTrayError::new(22))?;
+    let matches = constant_time_eq(&decrypted, plain_text);
+    decrypted.zeroize();
 
-    nebula::seeded_shuffle(&mut characters, seed as usize);
+    if !matches {
+        return Err(SystemTrayError::new(22));
+    }
 
-    let char_positions: HashMap<_, _> = characters.par_iter().enumerate().map(|(i, &c)| (c, i)).collect();
+    Ok(())
+}
 
+/// Encrypts borrowed content with the given version of the scheme.
+///
+/// # Arguments
+///
+/// * `plain_text` - The content to be encrypted.
+/// * `key1` - A secret key used for encryption.
+/// * `key2` - Another secret key used for encryption.
+/// * `version` - The version of the scheme, which has to be given again to `decrypt_file_versioned`.
+///
+/// # Returns
+///
+/// A `Result` containing the encrypted content, or an error if encryption fails.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::{encrypt_file_versioned, FormatVersion};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let plain_text = b"secret content".to_vec();
+/// let encrypted = encrypt_file_versioned(&plain_text, &key1, &key2, FormatVersion::V2)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt_file_versioned(plain_text: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, version: FormatVersion) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key1 = key1.expose();
+    let key2 = key2.expose();
+    check_keys(key1, key2)?;
+
+    let val1 = addition_chiffres(key2);
+    let val2 = addition_chiffres(key1);
 
-    let table_len = 256;
+    let seed = version.table_seed(key1, key2);
 
     let key1_chars: Vec<usize> = key1.into_par_iter().map(|&c| c as usize % 256).collect();
     let key2_chars: Vec<usize> = key2.into_par_iter().map(|&c| c as usize % 256).collect();
     let key1_len = KEY_LENGTH;
     let key2_len = KEY_LENGTH;
+    let tables = Substitutions::new(version, seed, &key1_chars, &key2_chars);
 
-    let mut cipher_text: Vec<_> = plain_text.par_iter().enumerate().filter_map(|(i, c)| {
-        let table_2d = key1_chars[i % key1_len] % table_len;
-        let row = key2_chars[i % key2_len] % table_len;
-
-        match char_positions.get(c) {
-            Some(col) => {
-                let col = col % 256;
+    let mut cipher_text = map_indexed(plain_text, |i, c| {
+        let table_2d = key1_chars[i % key1_len];
+        let row = key2_chars[i % key2_len];
 
-                if table_2d < table_len && row < table[table_2d].len() && col < table[table_2d][row].len() {
-                    Some(table[table_2d][row][col])
-                } else {
-                    None
-                }
-            },
-            None => {
-                println!("Character '{}' not found in character set", c);
-                None
-            },
-        }
-    }).collect();
+        tables.encrypt_byte(i, table_2d, row, c)
+    });
 
     xor_crypt3(&mut cipher_text, key1);
-    let vz = vz_maker(val1, val2, seed);
+    let vz = vz_maker(val1, val2, seed)?;
 
-    Ok(shift_bits(cipher_text, vz))
+    Ok(shift_bits(&cipher_text, vz))
 }
 
 
@@ -123,19 +451,21 @@ pub(crate) fn encrypt_file(plain_text: Vec<u8>, key1: &Secret<Vec<u8>>, key2: &S
 ///
 /// # Example
 ///
-/// ```
-/// use your_crate::decrypt_file;
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use horizon::cryptex::decrypt_file;
+/// use horizon::gene3;
+/// use horizon::keys::{RowKey, TableKey};
 ///
 /// // Read the encrypted content of the file
 /// let encrypted_content = std::fs::read("encrypted_file.txt").expect("Failed to read file");
 ///
-/// // Provide secret keys and a password
-/// let key1 = Secret::new(vec![1, 2, 3]);
-/// let key2 = Secret::new(vec![4, 5, 6]);
-/// let password = "my_password";
+/// // Derive the secret keys from the passwords used for encryption
+/// let key1 = TableKey::new(gene3(b"first password")?);
+/// let key2 = RowKey::new(gene3(b"second password")?);
 ///
 /// // Decrypt the content of the file
-/// let decrypted = decrypt_file(encrypted_content, &key1, &key2, password);
+/// let decrypted = decrypt_file(encrypted_content, &key1, &key2);
 ///
 /// match decrypted {
 ///     Ok(decrypted_content) => {
@@ -147,26 +477,92 @@ pub(crate) fn encrypt_file(plain_text: Vec<u8>, key1: &Secret<Vec<u8>>, key2: &S
 ///         eprintln!("Error occurred during decryption: {:?}", err);
 ///     }
 /// }
+/// # Ok(())
+/// # }
 /// ```
-pub(crate) fn decrypt_file(cipher_text: Vec<u8>, key1: &Secret<Vec<u8>>, key2: &Secret<Vec<u8>>) -> Result<Vec<u8>, Box<dyn Error>> {
+pub fn decrypt_file(cipher_text: Vec<u8>, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Vec<u8>, Box<dyn Error>> {
+    decrypt_file_ref(&cipher_text, key1, key2)
+}
 
+/// Decrypts borrowed content produced by `encrypt_file`.
+///
+/// The cipher text is left untouched, so the same slice can be decrypted again, e.g. to retry with other keys.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The encrypted content to be decrypted.
+/// * `key1` - A secret key used for decryption.
+/// * `key2` - Another secret key used for decryption.
+///
+/// # Returns
+///
+/// A `Result` containing the decrypted content, or an error if decryption fails.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 5 if a key is shorter than `KEY_LENGTH`. Every table row is a permutation,
+/// so no byte is ever dropped: the plain text has the length of the cipher text, null bytes included.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::{decrypt_file_ref, encrypt_file_ref};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let plain_text = b"secret content".to_vec();
+/// # let encrypted = encrypt_file_ref(&plain_text, &key1, &key2)?;
+/// let decrypted = decrypt_file_ref(&encrypted, &key1, &key2)?;
+/// # assert_eq!(decrypted, plain_text);
+/// # Ok(())
+/// # }
+/// ```
+pub fn decrypt_file_ref(cipher_text: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Vec<u8>, Box<dyn Error>> {
+    decrypt_file_versioned(cipher_text, key1, key2, FormatVersion::V1)
+}
 
-    let key1 = key1.expose_secret();
-    let key2 = key2.expose_secret();
+/// Decrypts borrowed content produced by `encrypt_file_versioned`.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The encrypted content to be decrypted.
+/// * `key1` - A secret key used for decryption.
+/// * `key2` - Another secret key used for decryption.
+/// * `version` - The version of the scheme given to `encrypt_file_versioned`.
+///
+/// # Returns
+///
+/// A `Result` containing the decrypted content, or an error if decryption fails.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::{decrypt_file_versioned, encrypt_file_versioned, FormatVersion};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let plain_text = b"secret content".to_vec();
+/// # let encrypted = encrypt_file_versioned(&plain_text, &key1, &key2, FormatVersion::V2)?;
+/// let decrypted = decrypt_file_versioned(&encrypted, &key1, &key2, FormatVersion::V2)?;
+/// # assert_eq!(decrypted, plain_text);
+/// # Ok(())
+/// # }
+/// ```
+pub fn decrypt_file_versioned(cipher_text: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, version: FormatVersion) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key1 = key1.expose();
+    let key2 = key2.expose();
+    check_keys(key1, key2)?;
 
     let val1 = addition_chiffres(key2);
     let val2 = addition_chiffres(key1);
 
-    let seed = val2 * val1 ;
-
-    let mut characters: Vec<u8> = (0..=255).collect();
-    nebula::seeded_shuffle(&mut characters, seed as usize);
-
-    let table = table3(256, seed);
+    let seed = version.table_seed(key1, key2);
 
-    let table_len = 256;
-
-    let vz = vz_maker(val1, val2, seed);
+    let vz = vz_maker(val1, val2, seed)?;
     let mut cipher_text = unshift_bits(cipher_text, vz);
     xor_crypt3(&mut cipher_text, key1);
 
@@ -174,18 +570,893 @@ pub(crate) fn decrypt_file(cipher_text: Vec<u8>, key1: &Secret<Vec<u8>>, key2: &
     let key2_chars: Vec<usize> = key2.into_par_iter().map(|&c| c as usize % 256).collect();
     let key1_len = KEY_LENGTH;
     let key2_len = KEY_LENGTH;
+    let tables = Substitutions::new(version, seed, &key1_chars, &key2_chars);
+
+    Ok(map_indexed(&cipher_text, |i, c| {
+        let table_2d = key1_chars[i % key1_len];
+        let row = key2_chars[i % key2_len];
+
+        tables.decrypt_byte(i, table_2d, row, c)
+    }))
+}
+
+/// Re-encrypts content produced by `encrypt_file_versioned` under a newer version of the scheme.
+///
+/// The cipher text does not record its version, so the current one has to be given. The intermediate plain text is
+/// zeroized before returning.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The encrypted content to be migrated.
+/// * `key1` - A secret key used for encryption.
+/// * `key2` - Another secret key used for encryption.
+/// * `from` - The version `cipher_text` was encrypted with.
+/// * `to` - The version of the returned cipher text, which has to be given to `decrypt_file_versioned`.
+///
+/// # Returns
+///
+/// A `Result` containing the content encrypted under `to`, or an error if decryption or encryption fails.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 27 if `to` is older than `from`.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::{decrypt_file_versioned, encrypt_file_versioned, migrate, FormatVersion};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let plain_text = b"secret content".to_vec();
+/// # let encrypted = encrypt_file_versioned(&plain_text, &key1, &key2, FormatVersion::V1)?;
+/// let migrated = migrate(&encrypted, &key1, &key2, FormatVersion::V1, FormatVersion::V3)?;
+/// # assert_eq!(decrypt_file_versioned(&migrated, &key1, &key2, FormatVersion::V3)?, plain_text);
+/// # Ok(())
+/// # }
+/// ```
+pub fn migrate(cipher_text: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, from: FormatVersion, to: FormatVersion) -> Result<Vec<u8>, Box<dyn Error>> {
+    if to < from {
+        return Err(Box::new(SystemTrayError::new(27)));
+    }
+
+    let mut plain_text = decrypt_file_versioned(cipher_text, key1, key2, from)?;
+    let migrated = encrypt_file_versioned(&plain_text, key1, key2, to);
+    plain_text.zeroize();
+
+    migrated
+}
 
-    let plain_text: Vec<_> = cipher_text.par_iter().enumerate().filter_map(|(i, c)| {
-        let table_2d = key1_chars[i % key1_len] % table_len;
-        let row = key2_chars[i % key2_len] % table_len;
+/// Checks that both keys are long enough to index every position, returning code 5 otherwise.
+pub(crate) fn check_keys(key1: &[u8], key2: &[u8]) -> Result<(), SystemTrayError> {
+    if key1.len() < KEY_LENGTH || key2.len() < KEY_LENGTH {
+        return Err(SystemTrayError::new(5));
+    }
 
-        if table_2d < table_len && row < table[table_2d].len() {
-            table[table_2d][row].iter().position(|x| x == c).map(|col| characters[col])
-        } else {
-            None
+    Ok(())
+}
+
+/// A restricted set of symbols the cipher text of `encrypt_alphabet` stays within.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alphabet {
+    symbols: Vec<u8>,
+}
+
+impl Alphabet {
+    /// Creates an alphabet from its symbols.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - The distinct bytes of the alphabet.
+    ///
+    /// # Returns
+    ///
+    /// The alphabet, or `SystemTrayError` code 18 if `symbols` is empty or has duplicates.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use horizon::cryptex::Alphabet;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let printable = Alphabet::new(&(b' '..=b'~').collect::<Vec<u8>>())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(symbols: &[u8]) -> Result<Self, SystemTrayError> {
+        let mut seen = [false; 256];
+        for &symbol in symbols {
+            if seen[symbol as usize] {
+                return Err(SystemTrayError::new(18));
+            }
+            seen[symbol as usize] = true;
+        }
+
+        if symbols.is_empty() {
+            return Err(SystemTrayError::new(18));
         }
-    }).collect();
 
-    Ok(plain_text)
+        Ok(Alphabet { symbols: symbols.to_vec() })
+    }
+
+    /// Returns the symbols of the alphabet.
+    pub fn symbols(&self) -> &[u8] {
+        &self.symbols
+    }
+
+    /// Shuffles the alphabet with `seed` and returns it with the position of each symbol in it.
+    fn shuffled(&self, seed: u64) -> (Vec<u8>, [Option<usize>; 256]) {
+        let mut shuffled = self.symbols.clone();
+        nebula::seeded_shuffle(&mut shuffled, seed as usize);
+
+        let mut positions = [None; 256];
+        for (position, &symbol) in shuffled.iter().enumerate() {
+            positions[symbol as usize] = Some(position);
+        }
+
+        (shuffled, positions)
+    }
 }
 
+/// Encrypts content made of the symbols of `alphabet` into a cipher text made of the same symbols.
+///
+/// Only the keyed substitution layer of `encrypt_file` is applied, over the shuffled alphabet instead of all 256 bytes:
+/// the XOR and bit shift layers would leave the alphabet. The cipher text has the same length as the content.
+///
+/// # Arguments
+///
+/// * `plain_text` - The content to be encrypted.
+/// * `key1` - A secret key used for encryption.
+/// * `key2` - Another secret key used for encryption.
+/// * `alphabet` - The symbols of both the content and the cipher text.
+///
+/// # Returns
+///
+/// A `Result` containing the encrypted content, or `SystemTrayError` code 6 if a byte is not in `alphabet`.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::{encrypt_alphabet, Alphabet};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// let base64 = Alphabet::new(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/")?;
+/// let encrypted = encrypt_alphabet(b"SGVsbG8", &key1, &key2, &base64)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt_alphabet(plain_text: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, alphabet: &Alphabet) -> Result<Vec<u8>, Box<dyn Error>> {
+    substitute_alphabet(plain_text, key1, key2, alphabet, true)
+}
+
+/// Decrypts content produced by `encrypt_alphabet` with the same alphabet.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The encrypted content.
+/// * `key1` - A secret key used for decryption.
+/// * `key2` - Another secret key used for decryption.
+/// * `alphabet` - The alphabet given to `encrypt_alphabet`.
+///
+/// # Returns
+///
+/// A `Result` containing the decrypted content, or `SystemTrayError` code 6 if a byte is not in `alphabet`.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::{decrypt_alphabet, encrypt_alphabet, Alphabet};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let base64 = Alphabet::new(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/")?;
+/// # let encrypted = encrypt_alphabet(b"SGVsbG8", &key1, &key2, &base64)?;
+/// let decrypted = decrypt_alphabet(&encrypted, &key1, &key2, &base64)?;
+/// # assert_eq!(decrypted, b"SGVsbG8");
+/// # Ok(())
+/// # }
+/// ```
+pub fn decrypt_alphabet(cipher_text: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, alphabet: &Alphabet) -> Result<Vec<u8>, Box<dyn Error>> {
+    substitute_alphabet(cipher_text, key1, key2, alphabet, false)
+}
+
+fn substitute_alphabet(input: &[u8], key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, alphabet: &Alphabet, encrypt: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key1 = key1.expose();
+    let key2 = key2.expose();
+    check_keys(key1, key2)?;
+
+    let seed = addition_chiffres(key1).wrapping_mul(addition_chiffres(key2));
+    let (shuffled, positions) = alphabet.shuffled(seed);
+    let size = shuffled.len();
+
+    map_indexed(input, |i, c| {
+        let col = positions[c as usize].ok_or_else(|| SystemTrayError::new(6))?;
+        let offset = (key1[i % KEY_LENGTH] as usize + key2[i % KEY_LENGTH] as usize) % size;
+
+        let position = if encrypt { (col + offset) % size } else { (col + size - offset) % size };
+        Ok(shuffled[position])
+    }).into_iter().collect::<Result<Vec<u8>, SystemTrayError>>().map_err(|err| err.into())
+}
+
+/// Encrypts content padded so that the cipher text length is a multiple of `block_align`.
+///
+/// The real length of the content is stored as a big-endian `u64` before it, and the padding is filled with random bytes.
+/// Both are encrypted along with the content, so `decrypt_file_aligned` can trim the padding.
+///
+/// # Arguments
+///
+/// * `plain_text` - The content to be encrypted.
+/// * `key1` - A secret key used for encryption.
+/// * `key2` - Another secret key used for encryption.
+/// * `block_align` - The block size the cipher text is padded to, or 0 for no padding. It does not have to be a power of two.
+///
+/// # Returns
+///
+/// A `Result` containing the padded encrypted content, or an error if encryption fails.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::encrypt_file_aligned;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let plain_text = b"secret content".to_vec();
+/// let encrypted = encrypt_file_aligned(plain_text, &key1, &key2, 4096)?;
+/// assert_eq!(encrypted.len() % 4096, 0);
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt_file_aligned(plain_text: Vec<u8>, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, block_align: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let padding = ciphertext_size(plain_text.len(), Some(block_align)) - ALIGNED_LENGTH_SIZE - plain_text.len();
+
+    let padding = match padding {
+        0 => Vec::new(),
+        _ => Nebula::new(secured_seed()?).generate_random_bytes(padding)?,
+    };
+    let padded = format::encode_aligned(&plain_text, &padding);
+
+    encrypt_file(padded, key1, key2)
+}
+
+/// Returns the length of the cipher text produced for a plain text of `plaintext_len` bytes, before encrypting it.
+///
+/// Without alignment, this is the output length of `encrypt_file` and its variants (`encrypt_file_versioned`,
+/// `encrypt_file_mode` and `BatchCipher`), which is the plain text length: there is no header, tag or framing.
+/// With `Some(block_align)`, it is the output length of `encrypt_file_aligned`, including the stored length and the padding.
+/// `encrypt3` is not covered, as the number of random stars it inserts is only known once drawn.
+///
+/// # Arguments
+///
+/// * `plaintext_len` - The length of the content to encrypt.
+/// * `block_align` - The `block_align` given to `encrypt_file_aligned`, or `None` for `encrypt_file`.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::{ciphertext_size, encrypt_file_aligned};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let plain_text = b"secret content".to_vec();
+/// let mut upload = Vec::with_capacity(ciphertext_size(plain_text.len(), Some(4096)));
+/// upload.extend(encrypt_file_aligned(plain_text, &key1, &key2, 4096)?);
+/// # assert_eq!(upload.len(), 4096);
+/// # Ok(())
+/// # }
+/// ```
+pub fn ciphertext_size(plaintext_len: usize, block_align: Option<usize>) -> usize {
+    match block_align {
+        None => plaintext_len,
+        Some(0) => ALIGNED_LENGTH_SIZE + plaintext_len,
+        Some(block_align) => (ALIGNED_LENGTH_SIZE + plaintext_len).next_multiple_of(block_align),
+    }
+}
+
+/// Decrypts content produced by `encrypt_file_aligned` and removes its padding.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The padded encrypted content.
+/// * `key1` - A secret key used for decryption.
+/// * `key2` - Another secret key used for decryption.
+///
+/// # Returns
+///
+/// A `Result` containing the decrypted content without padding, or an error if the decrypted length is invalid.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::cryptex::{decrypt_file_aligned, encrypt_file_aligned};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// # let plain_text = b"secret content".to_vec();
+/// # let encrypted = encrypt_file_aligned(plain_text.clone(), &key1, &key2, 4096)?;
+/// let decrypted = decrypt_file_aligned(encrypted, &key1, &key2)?;
+/// # assert_eq!(decrypted, plain_text);
+/// # Ok(())
+/// # }
+/// ```
+pub fn decrypt_file_aligned(cipher_text: Vec<u8>, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut padded = decrypt_file(cipher_text, key1, key2)?;
+    let plain_text = format::decode_aligned(&padded).map(<[u8]>::to_vec);
+    padded.zeroize();
+
+    Ok(plain_text?)
+}
+
+/// Encrypts and decrypts many messages under the same keys, deriving the tables and the shift key once.
+///
+/// `encrypt_file_versioned` builds the substitution and runs the Argon2 derivation of the shift key on every call,
+/// which dominates the cost of small messages. A `BatchCipher` does it in `new`, then only reads its state:
+/// it is `Sync`, so one instance can be shared by reference, such as through an `Arc`, by every thread of a server.
+/// The cipher has no nonce, so nothing is synchronized between calls.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use std::sync::Arc;
+/// # use horizon::cryptex::{BatchCipher, FormatVersion};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// let cipher = Arc::new(BatchCipher::new(&key1, &key2, FormatVersion::V2)?);
+///
+/// let worker = Arc::clone(&cipher);
+/// std::thread::spawn(move || worker.encrypt(b"message"));
+/// # Ok(())
+/// # }
+/// ```
+pub struct BatchCipher {
+    key1: Secret<Vec<u8>>,
+    key2: Secret<Vec<u8>>,
+    vz: Secret<Vec<u8>>,
+    tables: Substitutions,
+}
+
+impl BatchCipher {
+    /// Derives the state shared by every message encrypted under `key1`, `key2` and `version`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key1` - A secret key used for encryption.
+    /// * `key2` - Another secret key used for encryption.
+    /// * `version` - The format version, as given to `encrypt_file_versioned`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SystemTrayError` code 5 if a key is shorter than `KEY_LENGTH`.
+    pub fn new(key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, version: FormatVersion) -> Result<Self, SystemTrayError> {
+        let key1 = key1.expose();
+        let key2 = key2.expose();
+        check_keys(key1, key2)?;
+
+        let val1 = addition_chiffres(key2);
+        let val2 = addition_chiffres(key1);
+        let seed = version.table_seed(key1, key2);
+
+        let key1_chars: Vec<usize> = key1.iter().map(|&c| c as usize).collect();
+        let key2_chars: Vec<usize> = key2.iter().map(|&c| c as usize).collect();
+
+        Ok(BatchCipher {
+            key1: Secret::new(key1.to_vec()),
+            key2: Secret::new(key2.to_vec()),
+            vz: vz_maker(val1, val2, seed)?,
+            tables: Substitutions::new(version, seed, &key1_chars, &key2_chars),
+        })
+    }
+
+    /// Encrypts a message, giving the same output as `encrypt_file_versioned` with the keys and version of `new`.
+    pub fn encrypt(&self, plain_text: &[u8]) -> Vec<u8> {
+        let key1 = self.key1.expose_secret();
+        let key2 = self.key2.expose_secret();
+        let vz = self.vz.expose_secret();
+
+        map_indexed(plain_text, |i, c| {
+            let substituted = self.tables.encrypt_byte(i, key1[i % KEY_LENGTH] as usize, key2[i % KEY_LENGTH] as usize, c);
+            (substituted ^ key1[i % key1.len()]).rotate_left(vz[i % vz.len()] as u32)
+        })
+    }
+
+    /// Decrypts a message produced by `encrypt`, or by `encrypt_file_versioned` with the same keys and version.
+    pub fn decrypt(&self, cipher_text: &[u8]) -> Vec<u8> {
+        let key1 = self.key1.expose_secret();
+        let key2 = self.key2.expose_secret();
+        let vz = self.vz.expose_secret();
+
+        map_indexed(cipher_text, |i, c| {
+            let unshifted = c.rotate_right(vz[i % vz.len()] as u32) ^ key1[i % key1.len()];
+            self.tables.decrypt_byte(i, key1[i % KEY_LENGTH] as usize, key2[i % KEY_LENGTH] as usize, unshifted)
+        })
+    }
+}
+
+/// Decrypts the output of `encrypt_file` as it is read, see `decrypt_reader`.
+pub struct DecryptReader<R: Read> {
+    reader: R,
+    key1: Secret<Vec<u8>>,
+    key2: Secret<Vec<u8>>,
+    vz: Secret<Vec<u8>>,
+    tables: CipherTables,
+    /// Offset of the next byte in the cipher text, 64 bits wide so that content over 4 GiB works on 32-bit targets.
+    position: u64,
+}
+
+/// Wraps a reader of content produced by `encrypt_file`, yielding the decrypted content as it is read.
+///
+/// Every byte of the cipher is decrypted from its position alone, so large content can be processed
+/// incrementally without holding it in memory. The cipher text is not authenticated: a modified byte
+/// decrypts to a wrong byte, and no error is reported.
+///
+/// # Arguments
+///
+/// * `cipher_reader` - The source of the encrypted content.
+/// * `key1` - A secret key used for decryption.
+/// * `key2` - Another secret key used for decryption.
+///
+/// # Returns
+///
+/// A `DecryptReader` yielding the same bytes as `decrypt_file`, or an error if a key is too short.
+///
+/// # Example
+///
+/// ```no_run
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use std::fs::File;
+/// # use horizon::cryptex::decrypt_reader;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"cryptex key one")?);
+/// # let key2 = RowKey::new(gene3(b"cryptex key two")?);
+/// use std::io::{BufRead, BufReader};
+///
+/// let reader = decrypt_reader(File::open("encrypted.log")?, &key1, &key2)?;
+/// for line in BufReader::new(reader).lines() {
+///     println!("{}", line?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn decrypt_reader<R: Read>(cipher_reader: R, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<DecryptReader<R>, Box<dyn Error>> {
+    let key1 = key1.expose();
+    let key2 = key2.expose();
+    check_keys(key1, key2)?;
+
+    let val1 = addition_chiffres(key2);
+    let val2 = addition_chiffres(key1);
+    let seed = val2.wrapping_mul(val1);
+
+    Ok(DecryptReader {
+        reader: cipher_reader,
+        key1: Secret::new(key1.to_vec()),
+        key2: Secret::new(key2.to_vec()),
+        vz: vz_maker(val1, val2, seed)?,
+        tables: CipherTables::from_seed(seed),
+        position: 0,
+    })
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.reader.read(buf)?;
+
+        let key1 = self.key1.expose_secret();
+        let key2 = self.key2.expose_secret();
+        let vz = self.vz.expose_secret();
+
+        // Undo the same steps as `decrypt_file_versioned`, for one byte at a time
+        for (offset, byte) in (self.position..).zip(buf[..count].iter_mut()) {
+            let shift = vz[key_index(offset, vz.len())] as u32;
+            let unshifted = byte.rotate_right(shift) ^ key1[key_index(offset, key1.len())];
+            let table_2d = key1[key_index(offset, KEY_LENGTH)] as usize;
+            let row = key2[key_index(offset, KEY_LENGTH)] as usize;
+
+            *byte = self.tables.decrypt_byte(table_2d, row, unshifted);
+        }
+        self.position += count as u64;
+
+        Ok(count)
+    }
+}
+
+/// Maps an absolute offset in the content to an index in a key of `len` bytes.
+///
+/// The reduction is done in `u64`, so the index stays right past `usize::MAX` on 32-bit targets.
+fn key_index(offset: u64, len: usize) -> usize {
+    (offset % len as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use rand_core::RngCore;
+
+    use crate::gene3;
+    use crate::keys::derive_subkey;
+
+    use super::*;
+
+    #[test]
+    fn test_decrypt_file_ref_twice() {
+        let plain_text = b"borrowed cipher text can be decrypted more than once".to_vec();
+        let master = gene3(b"cryptex_master_key").unwrap();
+        let key1 = TableKey::new(derive_subkey(&master, b"key1"));
+        let key2 = RowKey::new(derive_subkey(&master, b"key2"));
+        let wrong_key2 = RowKey::new(derive_subkey(&master, b"wrong key2"));
+
+        let cipher_text = encrypt_file_ref(&plain_text, &key1, &key2).unwrap();
+        assert_eq!(cipher_text, encrypt_file(plain_text.clone(), &key1, &key2).unwrap());
+
+        let first_attempt = decrypt_file_ref(&cipher_text, &key1, &wrong_key2).unwrap();
+        let second_attempt = decrypt_file_ref(&cipher_text, &key1, &key2).unwrap();
+
+        assert_ne!(first_attempt, plain_text);
+        assert_eq!(second_attempt, plain_text);
+    }
+
+    #[test]
+    fn test_decrypt_file_keeps_null_bytes() {
+        let plain_text = b"no byte is lost\0 even the null ones\0".to_vec();
+        let key1 = TableKey::new(gene3(b"diag_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"diag_key_two").unwrap());
+
+        let cipher_text = encrypt_file_ref(&plain_text, &key1, &key2).unwrap();
+
+        assert_eq!(decrypt_file_ref(&cipher_text, &key1, &key2).unwrap(), plain_text);
+    }
+
+    #[test]
+    fn test_cipher_tables_inverse() {
+        let tables = CipherTables::from_seed(123_456_789);
+
+        for byte in 0..=255u8 {
+            assert_eq!(tables.inverse[tables.forward[byte as usize] as usize], byte);
+            assert_eq!(tables.forward[tables.inverse[byte as usize] as usize], byte);
+            assert_eq!(tables.decrypt_byte(17, 250, tables.encrypt_byte(17, 250, byte)), byte);
+        }
+    }
+
+    #[test]
+    fn test_cipher_tables_match_table3() {
+        let seed = 987_654_321;
+        let tables = CipherTables::from_seed(seed);
+        let table = crate::table3(256, seed);
+
+        for (table_2d, row) in [(0, 0), (3, 200), (255, 255)] {
+            for byte in 0..=255u8 {
+                let col = tables.forward[byte as usize] as usize;
+                assert_eq!(tables.encrypt_byte(table_2d, row, byte), table[table_2d][row][col]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ciphertext_size() {
+        let key1 = TableKey::new(gene3(b"size_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"size_key_two").unwrap());
+
+        for length in [0, 1, 7, 8, 100, 4088, 4089, 10_000] {
+            let plain_text = vec![0x61u8; length];
+            assert_eq!(ciphertext_size(length, None), encrypt_file_ref(&plain_text, &key1, &key2).unwrap().len());
+            assert_eq!(ciphertext_size(length, None), encrypt_file_mode(&plain_text, &key1, &key2, CipherMode::XorOnly { nonce: [7; XOR_NONCE_LENGTH] }).unwrap().len());
+
+            for block_align in [0, 1, 16, 1000, 4096] {
+                let cipher_text = encrypt_file_aligned(plain_text.clone(), &key1, &key2, block_align).unwrap();
+                assert_eq!(ciphertext_size(length, Some(block_align)), cipher_text.len(), "length {} align {}", length, block_align);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encrypt_file_aligned() {
+        let plain_text = b"padded up to the storage block size".to_vec();
+        let key1 = TableKey::new(gene3(b"aligned_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"aligned_key_two").unwrap());
+
+        for block_align in [512, 7, 1] {
+            let cipher_text = encrypt_file_aligned(plain_text.clone(), &key1, &key2, block_align).unwrap();
+            assert_eq!(cipher_text.len() % block_align, 0);
+            assert_eq!(decrypt_file_aligned(cipher_text, &key1, &key2).unwrap(), plain_text);
+        }
+
+        let cipher_text = encrypt_file_aligned(plain_text.clone(), &key1, &key2, 0).unwrap();
+        assert_eq!(cipher_text.len(), ALIGNED_LENGTH_SIZE + plain_text.len());
+        assert_eq!(decrypt_file_aligned(cipher_text, &key1, &key2).unwrap(), plain_text);
+
+        let forged = encrypt_file(u64::MAX.to_be_bytes().to_vec(), &key1, &key2).unwrap();
+        assert!(decrypt_file_aligned(forged, &key1, &key2).is_err());
+    }
+
+    #[test]
+    fn test_adverse_keys_return_errors() {
+        let plain_text = vec![7u8; KEY_LENGTH * 2];
+        let short_key = TableKey::new(Secret::new(vec![1u8; KEY_LENGTH - 1]));
+        let zero_key = TableKey::new(Secret::new(vec![0u8; KEY_LENGTH]));
+        let key2 = RowKey::new(gene3(b"adverse_key_two").unwrap());
+
+        assert!(encrypt_file(plain_text.clone(), &short_key, &key2).is_err());
+        assert!(decrypt_file(plain_text.clone(), &short_key, &key2).is_err());
+
+        let cipher_text = encrypt_file(plain_text.clone(), &zero_key, &key2).unwrap();
+        assert_eq!(decrypt_file(cipher_text, &zero_key, &key2).unwrap(), plain_text);
+    }
+
+    #[test]
+    fn test_decrypt_reader_lines() {
+        let lines: Vec<String> = (0..2000).map(|i| format!("log line {} with some padding text", i)).collect();
+        let plain_text = lines.join("\n").into_bytes();
+        let key1 = TableKey::new(gene3(b"reader_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"reader_key_two").unwrap());
+
+        let cipher_text = encrypt_file(plain_text, &key1, &key2).unwrap();
+        let reader = decrypt_reader(cipher_text.as_slice(), &key1, &key2).unwrap();
+
+        let decrypted: Vec<String> = BufReader::new(reader).lines().map(|line| line.unwrap()).collect();
+        assert_eq!(decrypted, lines);
+    }
+
+    #[test]
+    fn test_encrypt_alphabet_base64() {
+        let base64 = Alphabet::new(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/").unwrap();
+        let plain_text: Vec<u8> = (0..3000).map(|i| base64.symbols()[i * 7 % 64]).collect();
+        let key1 = TableKey::new(gene3(b"alphabet_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"alphabet_key_two").unwrap());
+
+        let cipher_text = encrypt_alphabet(&plain_text, &key1, &key2, &base64).unwrap();
+        assert_ne!(cipher_text, plain_text);
+        assert!(cipher_text.iter().all(|c| base64.symbols().contains(c)));
+        assert_eq!(decrypt_alphabet(&cipher_text, &key1, &key2, &base64).unwrap(), plain_text);
+
+        assert!(encrypt_alphabet(b"not base64!", &key1, &key2, &base64).is_err());
+        assert!(Alphabet::new(b"ABCA").is_err());
+        assert!(Alphabet::new(b"").is_err());
+    }
+
+    #[test]
+    fn test_non_256_tables_stay_in_bounds() {
+        // Together, the keys hold every byte value at every position modulo 256
+        let key1 = TableKey::new(Secret::new((0..KEY_LENGTH).map(|i| i as u8).collect()));
+        let key2 = RowKey::new(Secret::new((0..KEY_LENGTH).map(|i| (i / 2) as u8).collect()));
+
+        for symbols in [&b"0123456"[..], b"x", &(0..=254).collect::<Vec<u8>>()] {
+            let alphabet = Alphabet::new(symbols).unwrap();
+            let plain_text: Vec<u8> = (0..2 * KEY_LENGTH).map(|i| symbols[i % symbols.len()]).collect();
+
+            let cipher_text = encrypt_alphabet(&plain_text, &key1, &key2, &alphabet).unwrap();
+            assert!(cipher_text.iter().all(|c| symbols.contains(c)));
+            assert_eq!(decrypt_alphabet(&cipher_text, &key1, &key2, &alphabet).unwrap(), plain_text);
+        }
+
+        let table = crate::table3_keyed(17, 42);
+        assert_eq!(table.len(), 17);
+        for row in table.iter().flatten() {
+            let mut sorted = row.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, (0..17).collect::<Vec<u8>>());
+        }
+        assert!(std::panic::catch_unwind(|| crate::table3_keyed(257, 42)).is_err());
+    }
+
+    /// Builds a reproducible pseudo-random payload, with every other 4 KiB block zeroed if `null_heavy`.
+    fn large_payload(length: usize, null_heavy: bool) -> Vec<u8> {
+        let mut payload = vec![0u8; length];
+        blake3::Hasher::new().update(&(length as u64).to_be_bytes()).finalize_xof().fill(&mut payload);
+        if null_heavy {
+            payload.chunks_mut(4096).step_by(2).for_each(|block| block.fill(0));
+        }
+        payload
+    }
+
+    #[test]
+    fn test_large_round_trips() {
+        let key1 = TableKey::new(gene3(b"large_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"large_key_two").unwrap());
+
+        for (length, null_heavy) in [(1 << 20, false), (1 << 20, true), (5 << 20, false), (5 << 20, true)] {
+            // Not a multiple of KEY_LENGTH, so the last positions wrap around the keys mid-cycle
+            let plain_text = large_payload(length + 7, null_heavy);
+
+            let cipher_text = encrypt_file(plain_text.clone(), &key1, &key2).unwrap();
+            assert_eq!(cipher_text.len(), plain_text.len());
+
+            let decrypted = decrypt_file(cipher_text, &key1, &key2).unwrap();
+            assert!(decrypted == plain_text, "Round trip failed for {} bytes, null heavy: {}", length + 7, null_heavy);
+        }
+    }
+
+    #[test]
+    fn test_format_version_v2_seed() {
+        // Same byte sums: the V1 seed collides, the V2 one does not
+        assert_eq!(FormatVersion::V1.table_seed(&[1, 2], &[3]), FormatVersion::V1.table_seed(&[2, 1], &[3]));
+        assert_eq!(FormatVersion::V1.table_seed(&[1, 2], &[3]), FormatVersion::V1.table_seed(&[3], &[1, 2]));
+        assert_ne!(FormatVersion::V2.table_seed(&[1, 2], &[3]), FormatVersion::V2.table_seed(&[2, 1], &[3]));
+        assert_ne!(FormatVersion::V2.table_seed(&[1, 2], &[3]), FormatVersion::V2.table_seed(&[3], &[1, 2]));
+
+        let plain_text = b"the order of the key bytes matters in V2".to_vec();
+        let key1 = TableKey::new(gene3(b"version_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"version_key_two").unwrap());
+
+        let v1 = encrypt_file_versioned(&plain_text, &key1, &key2, FormatVersion::V1).unwrap();
+        let v2 = encrypt_file_versioned(&plain_text, &key1, &key2, FormatVersion::V2).unwrap();
+        assert_eq!(v1, encrypt_file_ref(&plain_text, &key1, &key2).unwrap());
+        assert_ne!(v1, v2);
+        assert_eq!(decrypt_file_versioned(&v2, &key1, &key2, FormatVersion::V2).unwrap(), plain_text);
+        assert_ne!(decrypt_file_versioned(&v2, &key1, &key2, FormatVersion::V1).unwrap(), plain_text);
+    }
+
+    #[test]
+    fn test_round_trip_keeps_length() {
+        let key1 = TableKey::new(gene3(b"length_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"length_key_two").unwrap());
+        let mut rng = crate::nebula::XofRng::new(b"length test inputs");
+
+        // Each round trip derives the shift key with Argon2 twice, 1000 inputs take close to a minute in debug
+        for _ in 0..100 {
+            let mut plain_text = vec![0u8; rng.next_u32() as usize % 600];
+            rng.fill_bytes(&mut plain_text);
+            // Half of the inputs are mostly null bytes, which decrypt3 would drop
+            if rng.next_u32() & 1 == 0 {
+                plain_text.iter_mut().for_each(|byte| *byte &= 0x01);
+            }
+
+            let cipher_text = encrypt_file_ref(&plain_text, &key1, &key2).unwrap();
+            assert_eq!(cipher_text.len(), plain_text.len());
+            assert_eq!(decrypt_file(cipher_text, &key1, &key2).unwrap().len(), plain_text.len());
+        }
+    }
+
+    #[test]
+    fn test_key_index_past_u32() {
+        let past = u32::MAX as u64 + 1;
+        assert_eq!(key_index(past, KEY_LENGTH), 0);
+        assert_eq!(key_index(past + 700, KEY_LENGTH), 700 % KEY_LENGTH);
+        assert_eq!(key_index(5 << 30, 48), ((5u64 << 30) % 48) as usize);
+        assert_eq!(key_index(u64::MAX, KEY_LENGTH), KEY_LENGTH - 1);
+
+        // 2^32 is a multiple of KEY_LENGTH: a reader resuming there uses the key bytes of offset 0
+        let plain_text = b"indices agree far into the file".repeat(30);
+        let key1 = TableKey::new(gene3(b"offset_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"offset_key_two").unwrap());
+        let cipher_text = encrypt_file_ref(&plain_text, &key1, &key2).unwrap();
+
+        let mut reader = decrypt_reader(cipher_text.as_slice(), &key1, &key2).unwrap();
+        reader.position = past;
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, plain_text);
+        assert_eq!(reader.position, past + plain_text.len() as u64);
+    }
+
+    #[test]
+    fn test_batch_cipher_shared_across_threads() {
+        fn assert_sync<T: Sync + Send>() {}
+        assert_sync::<BatchCipher>();
+
+        let key1 = TableKey::new(gene3(b"batch_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"batch_key_two").unwrap());
+
+        for version in [FormatVersion::V1, FormatVersion::V3] {
+            let cipher = BatchCipher::new(&key1, &key2, version).unwrap();
+            let message = b"same output as the one-shot functions".repeat(20);
+            assert_eq!(cipher.encrypt(&message), encrypt_file_versioned(&message, &key1, &key2, version).unwrap());
+        }
+
+        let cipher = BatchCipher::new(&key1, &key2, FormatVersion::V2).unwrap();
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let cipher = &cipher;
+                scope.spawn(move || {
+                    for i in 0..1000 {
+                        let message = format!("thread {} message {}", thread, i).into_bytes();
+                        let cipher_text = cipher.encrypt(&message);
+                        assert_ne!(cipher_text, message);
+                        assert_eq!(cipher.decrypt(&cipher_text), message);
+                    }
+                });
+            }
+        });
+
+        let short = RowKey::new(Secret::new(vec![1u8; 16]));
+        assert_eq!(BatchCipher::new(&key1, &short, FormatVersion::V1).err().unwrap().code, 5);
+    }
+
+    #[test]
+    fn test_cipher_mode_xor_only() {
+        let plain_text = b"cache entries only need obfuscation".repeat(40);
+        let key1 = TableKey::new(gene3(b"mode_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"mode_key_two").unwrap());
+
+        let mode = CipherMode::XorOnly { nonce: [1; XOR_NONCE_LENGTH] };
+        let xor_only = encrypt_file_mode(&plain_text, &key1, &key2, mode).unwrap();
+        let table = encrypt_file_mode(&plain_text, &key1, &key2, CipherMode::Table).unwrap();
+        assert_eq!(table, encrypt_file_ref(&plain_text, &key1, &key2).unwrap());
+        assert_ne!(xor_only, table);
+        assert_ne!(xor_only, plain_text);
+
+        assert_eq!(decrypt_file_mode(&xor_only, &key1, &key2, mode).unwrap(), plain_text);
+        assert_eq!(decrypt_file_mode(&table, &key1, &key2, CipherMode::Table).unwrap(), plain_text);
+        assert_ne!(decrypt_file_mode(&xor_only, &key1, &key2, CipherMode::Table).unwrap(), plain_text);
+        assert_ne!(decrypt_file_mode(&xor_only, &key1, &key2, CipherMode::XorOnly { nonce: [2; XOR_NONCE_LENGTH] }).unwrap(), plain_text);
+    }
+
+    #[test]
+    fn test_cipher_mode_xor_only_nonce() {
+        let key1 = TableKey::new(gene3(b"nonce_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"nonce_key_two").unwrap());
+        let first = b"first message under these keys".to_vec();
+        let second = b"other message under these keys".to_vec();
+        let plain_xor: Vec<u8> = first.iter().zip(&second).map(|(a, b)| a ^ b).collect();
+        let cipher_xor = |first_nonce, second_nonce| -> Vec<u8> {
+            let first = encrypt_file_mode(&first, &key1, &key2, CipherMode::XorOnly { nonce: first_nonce }).unwrap();
+            let second = encrypt_file_mode(&second, &key1, &key2, CipherMode::XorOnly { nonce: second_nonce }).unwrap();
+            first.iter().zip(&second).map(|(a, b)| a ^ b).collect()
+        };
+
+        // A reused nonce is a two-time pad, distinct nonces are not
+        assert_eq!(cipher_xor([3; XOR_NONCE_LENGTH], [3; XOR_NONCE_LENGTH]), plain_xor);
+        assert_ne!(cipher_xor([3; XOR_NONCE_LENGTH], [4; XOR_NONCE_LENGTH]), plain_xor);
+    }
+
+    #[test]
+    fn test_encrypt_verified() {
+        let mut plain_text = vec![0u8; 4096];
+        plain_text.extend_from_slice(b"mostly null bytes");
+        let key1 = TableKey::new(gene3(b"verified_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"verified_key_two").unwrap());
+
+        let cipher_text = encrypt_verified(&plain_text, &key1, &key2).unwrap();
+        assert_eq!(cipher_text, encrypt_file_ref(&plain_text, &key1, &key2).unwrap());
+
+        // A decryption giving other bytes, here under another key, is caught
+        let other_key2 = RowKey::new(gene3(b"verified_key_other").unwrap());
+        let error = check_round_trip(&plain_text, &cipher_text, |cipher_text| decrypt_file_ref(cipher_text, &key1, &other_key2)).unwrap_err();
+        assert_eq!(error.code, 22);
+    }
+
+    #[test]
+    fn test_format_version_v3_keyed_rows() {
+        let plain_text = b"every row of V3 is its own permutation".repeat(20);
+        let key1 = TableKey::new(gene3(b"version_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"version_key_two").unwrap());
+
+        let v2 = encrypt_file_versioned(&plain_text, &key1, &key2, FormatVersion::V2).unwrap();
+        let v3 = encrypt_file_versioned(&plain_text, &key1, &key2, FormatVersion::V3).unwrap();
+        assert_ne!(v2, v3);
+        assert_eq!(decrypt_file_versioned(&v3, &key1, &key2, FormatVersion::V3).unwrap(), plain_text);
+        assert_ne!(decrypt_file_versioned(&v3, &key1, &key2, FormatVersion::V2).unwrap(), plain_text);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let key1 = TableKey::new(gene3(b"migrate_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"migrate_key_two").unwrap());
+        let plain_text = b"Written under the first version of the scheme".to_vec();
+
+        let v1 = encrypt_file_versioned(&plain_text, &key1, &key2, FormatVersion::V1).unwrap();
+        let v2 = migrate(&v1, &key1, &key2, FormatVersion::V1, FormatVersion::V2).unwrap();
+
+        assert_eq!(v2, encrypt_file_versioned(&plain_text, &key1, &key2, FormatVersion::V2).unwrap());
+        assert_eq!(decrypt_file_versioned(&v2, &key1, &key2, FormatVersion::V2).unwrap(), plain_text);
+        assert_ne!(decrypt_file_versioned(&v2, &key1, &key2, FormatVersion::V1).unwrap(), plain_text);
+
+        let downgrade = migrate(&v2, &key1, &key2, FormatVersion::V2, FormatVersion::V1).unwrap_err();
+        assert_eq!(downgrade.downcast_ref::<SystemTrayError>().unwrap().code, 27);
+    }
+}
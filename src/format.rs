@@ -0,0 +1,194 @@
+//! The byte layouts written by the crate, in one place.
+//!
+//! All integers are stored in big-endian order.
+//!
+//! # Cipher text
+//!
+//! `encrypt_file` and its variants write one byte per plain text byte, with no header: the format version,
+//! the keys and the length are not recorded. Byte `i` of the plain text goes through, in order:
+//!
+//! 1. the substitution keyed by byte `i % KEY_LENGTH` of both keys, see `CipherTables` and `FormatVersion`,
+//! 2. a XOR with byte `i % KEY_LENGTH` of the first key,
+//! 3. a left rotation by the matching byte of the shift key derived by `vz_maker`.
+//!
+//! `encrypt3` inserts null bytes at random positions before these stages, and rotates the XOR key left by
+//! `seed % 64` bytes. Its output is therefore longer than the plain text and not readable by `decrypt_file`.
+//! As `decrypt3` removes every null byte, `encrypt3` rejects plain text that holds one.
+//!
+//! # Aligned payload
+//!
+//! `encrypt_file_aligned` encrypts, as one cipher text:
+//!
+//! | Bytes         | Content                       |
+//! |---------------|-------------------------------|
+//! | 8             | length of the content (`u64`) |
+//! | length        | content                       |
+//! | up to the end | random padding                |
+//!
+//! # Log
+//!
+//! `LogWriter` appends records one after the other, each being:
+//!
+//! | Bytes  | Content                                 |
+//! |--------|-----------------------------------------|
+//! | 8      | length of the cipher text (`u64`)       |
+//! | length | `encrypt_file` output of the record     |
+//!
+//! # Archive
+//!
+//! `ArchiveWriter` writes the encrypted blobs one after the other, then the index table:
+//!
+//! | Bytes                    | Content                                                          |
+//! |--------------------------|------------------------------------------------------------------|
+//! | 8                        | entry count (`u64`)                                              |
+//! | 20 + metadata, per entry | offset (`u64`), length (`u64`), metadata length (`u32`), metadata |
+//! | 8                        | offset of the index table (`u64`), the trailer                   |
+
+use crate::archive::ArchiveEntry;
+use crate::systemtrayerror::SystemTrayError;
+
+/// Size in bytes of the length stored before the content of an aligned payload.
+pub const ALIGNED_LENGTH_SIZE: usize = 8;
+
+/// Size in bytes of the length stored before each record of a log.
+pub const RECORD_LENGTH_SIZE: usize = 8;
+
+/// Size in bytes of the trailer holding the offset of the index table of an archive.
+pub const TRAILER_SIZE: u64 = 8;
+
+/// Builds an aligned payload: the length of `content`, `content`, then `padding`.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::format::encode_aligned;
+/// let payload = encode_aligned(b"abc", &[0xEE; 5]);
+/// assert_eq!(payload.len(), 16);
+/// ```
+pub fn encode_aligned(content: &[u8], padding: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(ALIGNED_LENGTH_SIZE + content.len() + padding.len());
+    payload.extend_from_slice(&(content.len() as u64).to_be_bytes());
+    payload.extend_from_slice(content);
+    payload.extend_from_slice(padding);
+    payload
+}
+
+/// Returns the content of an aligned payload, without its length and padding.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 16 if the payload is shorter than its length field, or than the length it stores.
+pub fn decode_aligned(payload: &[u8]) -> Result<&[u8], SystemTrayError> {
+    if payload.len() < ALIGNED_LENGTH_SIZE {
+        return Err(SystemTrayError::new(16));
+    }
+
+    let (length, rest) = payload.split_at(ALIGNED_LENGTH_SIZE);
+    let length = u64::from_be_bytes(length.try_into().unwrap());
+    if length > rest.len() as u64 {
+        return Err(SystemTrayError::new(16));
+    }
+
+    Ok(&rest[..length as usize])
+}
+
+/// Serializes the index table of an archive, followed by its trailer.
+///
+/// # Arguments
+///
+/// * `entries` - The entries of the archive.
+/// * `index_offset` - The position of the index table, right after the last blob.
+pub fn encode_index(entries: &[ArchiveEntry], index_offset: u64) -> Vec<u8> {
+    let mut index = Vec::new();
+    index.extend_from_slice(&(entries.len() as u64).to_be_bytes());
+    for entry in entries {
+        index.extend_from_slice(&entry.offset.to_be_bytes());
+        index.extend_from_slice(&entry.length.to_be_bytes());
+        index.extend_from_slice(&(entry.metadata.len() as u32).to_be_bytes());
+        index.extend_from_slice(&entry.metadata);
+    }
+    index.extend_from_slice(&index_offset.to_be_bytes());
+    index
+}
+
+/// Parses an index table, without its trailer, checking that every entry lies before the table.
+///
+/// # Arguments
+///
+/// * `index` - The bytes between `index_offset` and the trailer.
+/// * `index_offset` - The position of the index table, read from the trailer.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 11 if the table is truncated or an entry points past the blobs.
+pub fn decode_index(mut index: &[u8], index_offset: u64) -> Result<Vec<ArchiveEntry>, SystemTrayError> {
+    let count = take_u64(&mut index)?;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let offset = take_u64(&mut index)?;
+        let length = take_u64(&mut index)?;
+        let metadata_len = u32::from_be_bytes(take(&mut index, 4)?.try_into().unwrap()) as usize;
+        let metadata = take(&mut index, metadata_len)?.to_vec();
+
+        if offset.checked_add(length).is_none_or(|end| end > index_offset) {
+            return Err(SystemTrayError::new(11));
+        }
+
+        entries.push(ArchiveEntry { offset, length, metadata });
+    }
+
+    Ok(entries)
+}
+
+/// Reads a big-endian `u64`, returning code 11 if the bytes run out.
+fn take_u64(bytes: &mut &[u8]) -> Result<u64, SystemTrayError> {
+    Ok(u64::from_be_bytes(take(bytes, 8)?.try_into().unwrap()))
+}
+
+fn take<'a>(bytes: &mut &'a [u8], count: usize) -> Result<&'a [u8], SystemTrayError> {
+    if bytes.len() < count {
+        return Err(SystemTrayError::new(11));
+    }
+
+    let (taken, rest) = bytes.split_at(count);
+    *bytes = rest;
+    Ok(taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aligned_layout() {
+        let payload = encode_aligned(b"abc", &[0xEE, 0xEE]);
+        assert_eq!(payload, [0, 0, 0, 0, 0, 0, 0, 3, b'a', b'b', b'c', 0xEE, 0xEE]);
+        assert_eq!(decode_aligned(&payload).unwrap(), b"abc");
+
+        assert_eq!(decode_aligned(&payload[..7]).unwrap_err().code, 16);
+        assert_eq!(decode_aligned(&payload[..10]).unwrap_err().code, 16);
+    }
+
+    #[test]
+    fn test_index_layout() {
+        let entries = vec![
+            ArchiveEntry { offset: 0, length: 5, metadata: b"a.txt".to_vec() },
+            ArchiveEntry { offset: 5, length: 0x0102, metadata: Vec::new() },
+        ];
+
+        let index = encode_index(&entries, 0x0107);
+        let expected: Vec<u8> = [
+            &[0, 0, 0, 0, 0, 0, 0, 2][..],
+            &[0, 0, 0, 0, 0, 0, 0, 0], &[0, 0, 0, 0, 0, 0, 0, 5], &[0, 0, 0, 5], b"a.txt",
+            &[0, 0, 0, 0, 0, 0, 0, 5], &[0, 0, 0, 0, 0, 0, 1, 2], &[0, 0, 0, 0],
+            &[0, 0, 0, 0, 0, 0, 1, 7],
+        ].concat();
+        assert_eq!(index, expected);
+
+        let table = &index[..index.len() - TRAILER_SIZE as usize];
+        assert_eq!(decode_index(table, 0x0107).unwrap(), entries);
+        assert_eq!(decode_index(table, 0x0106).unwrap_err().code, 11);
+        assert_eq!(decode_index(&table[..table.len() - 1], 0x0107).unwrap_err().code, 11);
+    }
+}
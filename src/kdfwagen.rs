@@ -1,10 +1,17 @@
-use blake3::Hasher;
 use rayon::iter::IntoParallelRefMutIterator;
 use rayon::iter::IndexedParallelIterator;
 use rayon::prelude::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
+#[cfg(feature = "generic-array")]
+use generic_array::{ArrayLength, GenericArray};
+#[cfg(feature = "generic-array")]
+use secrecy::ExposeSecret;
 use secrecy::Secret;
 
+use crate::keys::check_key_strength;
+use crate::systemtrayerror::SystemTrayError;
+use crate::xof;
+
 /// Computes the Hash-based Message Authentication Code (HMAC) using the SHA3-512 hashing algorithm.
 ///
 /// # Parameters
@@ -18,7 +25,7 @@ use secrecy::Secret;
 ///
 /// # Examples
 ///
-/// ```rust
+/// ```ignore
 /// let key = vec![/* vector of u8 representing key */];
 /// let message = vec![/* vector of u8 representing message */];
 /// let hmac_result = hmac(&key, &message);
@@ -26,10 +33,8 @@ use secrecy::Secret;
 /// ```
 fn hmac(key: &[u8], message: &[u8], block_size: usize, output_size: usize) -> Vec<u8> {
     let mut adjusted_key = if key.len() > block_size {
-        let mut hasher = Hasher::new();
-        hasher.update(key);
         let mut output = vec![0; output_size];
-        hasher.finalize_xof().fill(&mut output);
+        xof(key, &mut output);
         output
     } else {
         let mut output = vec![0; output_size];
@@ -51,20 +56,27 @@ fn hmac(key: &[u8], message: &[u8], block_size: usize, output_size: usize) -> Ve
 
     let inner_input: Vec<u8> = ipad.into_iter().chain(message.iter().cloned()).collect();
 
-    let mut inner_hasher = Hasher::new();
-    inner_hasher.update(&inner_input);
     let mut inner_hash = vec![0; output_size];
-    inner_hasher.finalize_xof().fill(&mut inner_hash);
+    xof(&inner_input, &mut inner_hash);
 
     let outer_input: Vec<u8> = opad.into_iter().chain(inner_hash.iter().cloned()).collect();
-    let mut outer_hasher = Hasher::new();
-    outer_hasher.update(&outer_input);
     let mut outer_hash = vec![0; output_size];
-    outer_hasher.finalize_xof().fill(&mut outer_hash);
+    xof(&outer_input, &mut outer_hash);
 
     outer_hash
 }
 
+/// Output size in bytes of one PRF block.
+const PRF_OUTPUT_SIZE: usize = 64;
+/// Default length in bytes of the derived key.
+const KEY_LENGTH: usize = 512;
+const BLOCK_SIZE: usize = 128;
+const OUTPUT_SIZE: usize = 64;
+/// Maximum number of PRF blocks the KDF can chain.
+const MAX_BLOCK_COUNT: usize = 255;
+/// Longest key the KDF can derive without padding.
+pub const MAX_KDF_LENGTH: usize = MAX_BLOCK_COUNT * PRF_OUTPUT_SIZE;
+
 /// Performs the Key Derivation Function (KDF) based on the HMAC-SHA3-512 algorithm.
 ///
 /// # Parameters
@@ -77,28 +89,61 @@ fn hmac(key: &[u8], message: &[u8], block_size: usize, output_size: usize) -> Ve
 ///
 /// Returns the derived key as a vector of unsigned 8-bit integers.
 ///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 19 if the derived key fails `check_key_strength`.
+///
 /// # Examples
 ///
-/// ```rust
+/// ```ignore
 /// let password = vec![/* vector of u8 representing password */];
 /// let salt = vec![/* vector of u8 representing salt */];
 /// let iterations = 1000;
-/// let derived_key = kdfwagen(&password, &salt, iterations);
+/// let derived_key = kdfwagen(&password, &salt, iterations)?;
 /// println!("{:?}", derived_key);
 /// ```
-pub(crate) fn kdfwagen(password: &[u8], salt: &[u8], iterations: usize) -> Secret<Vec<u8>> {
-    const PRF_OUTPUT_SIZE: usize = 64;
-    const KEY_LENGTH: usize = 512;
-    const BLOCK_SIZE: usize = 128;
-    const OUTPUT_SIZE: usize = 64;
-
-    let mut result = Vec::new();
-    let mut block_count = (KEY_LENGTH + PRF_OUTPUT_SIZE - 1) / PRF_OUTPUT_SIZE;
+pub(crate) fn kdfwagen(password: &[u8], salt: &[u8], iterations: usize) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+    kdfwagen_with_length(password, salt, iterations, KEY_LENGTH)
+}
 
-    if block_count > 255 {
-        block_count = 255;
+/// Performs the Key Derivation Function (KDF) for a key of the requested length.
+///
+/// # Parameters
+///
+/// - `password`: A slice of unsigned 8-bit integers representing the password.
+/// - `salt`: A slice of unsigned 8-bit integers representing the salt.
+/// - `iterations`: The number of iterations for the KDF.
+/// - `length`: The length in bytes of the derived key.
+///
+/// # Returns
+///
+/// Returns the derived key as a vector of unsigned 8-bit integers.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 13 if `length` is greater than `MAX_KDF_LENGTH`, as the KDF
+/// cannot chain more than 255 blocks and the key would otherwise end with zeros.
+/// Returns code 19 if the derived key fails `check_key_strength`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use horizon::kdfwagen::kdfwagen_with_length;
+/// # use secrecy::ExposeSecret;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let derived_key = kdfwagen_with_length(b"password", b"salt", 1000, 1024)?;
+/// assert_eq!(derived_key.expose_secret().len(), 1024);
+/// # Ok(())
+/// # }
+/// ```
+pub fn kdfwagen_with_length(password: &[u8], salt: &[u8], iterations: usize, length: usize) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+    if length > MAX_KDF_LENGTH {
+        return Err(SystemTrayError::new(13));
     }
 
+    let mut result = Vec::new();
+    let block_count = length.div_ceil(PRF_OUTPUT_SIZE);
+
     for block_index in 1..=block_count {
         let mut block = salt.to_vec();
         block.extend_from_slice(&block_index.to_be_bytes());
@@ -110,11 +155,50 @@ pub(crate) fn kdfwagen(password: &[u8], salt: &[u8], iterations: usize) -> Secre
             u.par_iter_mut().zip(x.par_iter()).for_each(|(a, b)| *a ^= b);
         }
 
-        result.extend_from_slice(&u[..std::cmp::min(PRF_OUTPUT_SIZE, KEY_LENGTH)]);
+        result.extend_from_slice(&u[..PRF_OUTPUT_SIZE]);
     }
 
-    result.resize(KEY_LENGTH, 0);
-    Secret::new(result)
+    result.truncate(length);
+    check_key_strength(&result)?;
+    Ok(Secret::new(result))
+}
+
+/// Performs the Key Derivation Function (KDF) for a key whose length is part of its type.
+///
+/// This suits crates taking keys as `GenericArray<u8, U32>` and the like, without a runtime length check on their side.
+///
+/// # Parameters
+///
+/// - `password`: A slice of unsigned 8-bit integers representing the password.
+/// - `salt`: A slice of unsigned 8-bit integers representing the salt.
+/// - `iterations`: The number of iterations for the KDF.
+///
+/// # Returns
+///
+/// Returns the derived key as an array of `N` bytes.
+///
+/// # Errors
+///
+/// Returns the errors of `kdfwagen_with_length`, code 13 if `N` is greater than `MAX_KDF_LENGTH`.
+///
+/// # Examples
+///
+/// ```rust
+/// use generic_array::typenum::U32;
+/// # use horizon::kdfwagen::kdfwagen_array;
+/// # use secrecy::ExposeSecret;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+///
+/// let derived_key = kdfwagen_array::<U32>(b"password", b"salt", 1000)?;
+/// // A `GenericArray` key, as taken by `ChaCha20Poly1305::new`
+/// assert_eq!(derived_key.expose_secret().len(), 32);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "generic-array")]
+pub fn kdfwagen_array<N: ArrayLength<u8>>(password: &[u8], salt: &[u8], iterations: usize) -> Result<Secret<GenericArray<u8, N>>, SystemTrayError> {
+    let key = kdfwagen_with_length(password, salt, iterations, N::USIZE)?;
+    Ok(Secret::new(GenericArray::clone_from_slice(key.expose_secret())))
 }
 
 #[cfg(test)]
@@ -138,7 +222,38 @@ mod tests {
         let salt = b"salt";
         let iterations = 2;
         let expected = "413bd0ade22416e8e3d020ce630195a1344007b5ae5f7b80f4c8000954df962f0de0e577870cdb0b740cb40bbb3036e98d5a441cc9a23e6792c38d1c62d9e68ce44cb1b069bf2111c6f239260bc8a303ff27feec4712cf2eb6f77bbb2e57cde79367bb9db9b7deeaabef96bb26d7ad5958b4f29b26f7ed2bd80406aef4b0ebed6fee5f2ecf334ee5572028d563a42512bcc21be613aaf873c1b14b566c2747ca6fa9ef5542c2872fca20f71430f5a6db219ee5fb796fc991539763b3c2fe631ae1faa850ca7c184967bb4248fb2d8aaf633bf4b6c6ad76eeeb10ad1e42a104d7c2f07017e9812b01ee9c601cf4c45becac0d62bf33eaaed7ae92b5d93736cb66bfed9dbb2091334a883c6f4c65731bb1187bf186ca67c9e43954c4602d14efd3321c6e8cb4501bb81256def8f63ff5f0ebdbbec62e41be0e849be79f3caeac391f4aec954c9dda8a30a41b56e062a601dc9c3dbf6b0e4958b6a8528f673082fd5072caadf970cfc1cba9aa789b2c5f3e57cc12cd43284275d4e8bccc1a001d8e8f3c052589d2c9441c0df8c9fc4d3ef4a3a9f8cd523d5e1b2c96425bb3b415b5bb22070c9349421c9746f65e31331aab58950b4722c98d422cc88c1ab4601011c1d29db969edca4000e130ea788bef2de34e6856088f6a61df8545f55b174234702b22564710e99dea7cd55d01ce24f10f612424b0ea1bdc77c1cceb6774af4b";
-        let result = kdfwagen(password, salt, iterations);
+        let result = kdfwagen(password, salt, iterations).unwrap();
         assert_eq!(hex::encode(result.expose_secret()), expected);
     }
+
+    #[test]
+    fn test_kdfwagen_with_length() {
+        let key = kdfwagen_with_length(b"password", b"salt", 2, 100).unwrap();
+        assert_eq!(key.expose_secret().len(), 100);
+        assert_eq!(key.expose_secret()[..], kdfwagen(b"password", b"salt", 2).unwrap().expose_secret()[..100]);
+
+        let key = kdfwagen_with_length(b"password", b"salt", 2, MAX_KDF_LENGTH).unwrap();
+        assert_eq!(key.expose_secret().len(), MAX_KDF_LENGTH);
+        assert_ne!(key.expose_secret()[MAX_KDF_LENGTH - PRF_OUTPUT_SIZE..], [0u8; PRF_OUTPUT_SIZE]);
+    }
+
+    #[test]
+    fn test_kdfwagen_length_unsupported() {
+        let result = kdfwagen_with_length(b"password", b"salt", 2, 20000);
+        assert_eq!(result.err().map(|err| err.code), Some(13));
+    }
+
+    #[test]
+    #[cfg(feature = "generic-array")]
+    fn test_kdfwagen_array() {
+        use generic_array::typenum::{U32, U64};
+
+        let key32: Secret<GenericArray<u8, U32>> = kdfwagen_array(b"password", b"salt", 2).unwrap();
+        let key64: Secret<GenericArray<u8, U64>> = kdfwagen_array(b"password", b"salt", 2).unwrap();
+        let expected = kdfwagen_with_length(b"password", b"salt", 2, 64).unwrap();
+
+        assert_eq!(key32.expose_secret().len(), 32);
+        assert_eq!(key64.expose_secret().as_slice(), expected.expose_secret().as_slice());
+        assert_eq!(key32.expose_secret().as_slice(), &expected.expose_secret()[..32]);
+    }
 }
@@ -0,0 +1,609 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use blake3::Hasher;
+use rand_core::RngCore;
+use rayon::prelude::*;
+use secrecy::{ExposeSecret, Secret, Zeroize};
+
+use crate::kdfwagen::kdfwagen_with_length;
+use crate::nebula::{Nebula, secured_seed};
+use crate::systemtrayerror::SystemTrayError;
+use crate::{gene3, get_salt, KEY_LENGTH};
+
+/// Bytes of a secret key, as read by the cipher.
+///
+/// Implemented for `Secret<Vec<u8>>`, the keys derived by this crate. Implement it for another secret type,
+/// such as a handle on a key kept in an HSM, to wrap it in a `TableKey` or a `RowKey` without copying it
+/// into a `Secret` first.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::cryptex::encrypt_file_ref;
+/// # use horizon::gene3;
+/// # use horizon::keys::{KeyMaterial, RowKey, TableKey};
+/// # use secrecy::ExposeSecret;
+/// # struct HsmHandle(Vec<u8>);
+/// # impl HsmHandle {
+/// #     fn mapped_bytes(&self) -> &[u8] {
+/// #         &self.0
+/// #     }
+/// # }
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let table = HsmHandle(gene3(b"hsm table key")?.expose_secret().clone());
+/// # let row = HsmHandle(gene3(b"hsm row key")?.expose_secret().clone());
+/// # let plain_text = b"kept in the HSM".to_vec();
+/// struct HsmKey(HsmHandle);
+///
+/// impl KeyMaterial for HsmKey {
+///     fn expose(&self) -> &[u8] {
+///         self.0.mapped_bytes()
+///     }
+/// }
+///
+/// let encrypted = encrypt_file_ref(&plain_text, &TableKey::new(HsmKey(table)), &RowKey::new(HsmKey(row)))?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait KeyMaterial {
+    /// Returns the bytes of the key.
+    fn expose(&self) -> &[u8];
+}
+
+impl KeyMaterial for Secret<Vec<u8>> {
+    fn expose(&self) -> &[u8] {
+        self.expose_secret()
+    }
+}
+
+/// The first key of the cipher: selects the 2D table and keys the XOR layer.
+///
+/// Wrapping the key in its own type makes the role explicit in the `encrypt`/`decrypt` signatures,
+/// so the compiler rejects a `RowKey` passed where a `TableKey` is expected, as checked by `tests/ui/keys_swapped.rs`:
+///
+/// ```ignore
+/// let table_key = TableKey::new(gene3(b"first password")?);
+/// let row_key = RowKey::new(gene3(b"second password")?);
+///
+/// // Does not compile: the keys are swapped
+/// encrypt_file_ref(&plain_text, &row_key, &table_key);
+/// ```
+///
+/// The key material defaults to `Secret<Vec<u8>>`, any other `KeyMaterial` can be wrapped.
+pub struct TableKey<K = Secret<Vec<u8>>>(K);
+
+/// The second key of the cipher: selects the row inside the 2D table.
+pub struct RowKey<K = Secret<Vec<u8>>>(K);
+
+impl<K: KeyMaterial> TableKey<K> {
+    /// Wraps a secret key to be used as the table key.
+    pub fn new(key: K) -> Self {
+        TableKey(key)
+    }
+}
+
+impl TableKey {
+
+    /// Derives the table key from a master key.
+    ///
+    /// # Arguments
+    ///
+    /// * `master` - The master key the table key is derived from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use horizon::gene3;
+    /// # use horizon::keys::TableKey;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let master = gene3(b"my_password")?;
+    /// let table_key = TableKey::derive(&master);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn derive(master: &impl KeyMaterial) -> Self {
+        TableKey(derive_subkey(master, b"table key"))
+    }
+
+    /// Unwraps the underlying secret key.
+    pub fn into_secret(self) -> Secret<Vec<u8>> {
+        self.0
+    }
+}
+
+impl<K: KeyMaterial> RowKey<K> {
+    /// Wraps a secret key to be used as the row key.
+    pub fn new(key: K) -> Self {
+        RowKey(key)
+    }
+}
+
+impl RowKey {
+
+    /// Derives the row key from a master key.
+    ///
+    /// # Arguments
+    ///
+    /// * `master` - The master key the row key is derived from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use horizon::gene3;
+    /// # use horizon::keys::RowKey;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let master = gene3(b"my_password")?;
+    /// let row_key = RowKey::derive(&master);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn derive(master: &impl KeyMaterial) -> Self {
+        RowKey(derive_subkey(master, b"row key"))
+    }
+
+    /// Unwraps the underlying secret key.
+    pub fn into_secret(self) -> Secret<Vec<u8>> {
+        self.0
+    }
+}
+
+impl ExposeSecret<Vec<u8>> for TableKey {
+    fn expose_secret(&self) -> &Vec<u8> {
+        self.0.expose_secret()
+    }
+}
+
+impl ExposeSecret<Vec<u8>> for RowKey {
+    fn expose_secret(&self) -> &Vec<u8> {
+        self.0.expose_secret()
+    }
+}
+
+impl<K: KeyMaterial> KeyMaterial for TableKey<K> {
+    fn expose(&self) -> &[u8] {
+        self.0.expose()
+    }
+}
+
+impl<K: KeyMaterial> KeyMaterial for RowKey<K> {
+    fn expose(&self) -> &[u8] {
+        self.0.expose()
+    }
+}
+
+/// Derives an independent subkey from a master key.
+///
+/// The subkey is the BLAKE3 XOF output of the master key in key derivation mode, separated by `context`,
+/// so different contexts give unrelated keys and the master key cannot be recovered from a subkey.
+///
+/// # Arguments
+///
+/// * `master` - The master key.
+/// * `context` - A label distinguishing the subkey from the other subkeys of the same master key.
+///
+/// # Returns
+///
+/// A `KEY_LENGTH` bytes subkey.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::derive_subkey;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let master = gene3(b"my_password")?;
+/// let round_key = derive_subkey(&master, &1u32.to_be_bytes());
+/// # Ok(())
+/// # }
+/// ```
+pub fn derive_subkey(master: &impl KeyMaterial, context: &[u8]) -> Secret<Vec<u8>> {
+    let mut hasher = Hasher::new_derive_key("horizon subkey derivation");
+    hasher.update(&(context.len() as u64).to_be_bytes());
+    hasher.update(context);
+    hasher.update(master.expose());
+
+    let mut subkey = vec![0u8; KEY_LENGTH];
+    hasher.finalize_xof().fill(&mut subkey);
+    Secret::new(subkey)
+}
+
+/// Length of a run of the same byte from which a derived key is rejected as weak.
+///
+/// A run this long has a probability around `256^-15` per position in a random key, it only shows up
+/// when the derivation went wrong, e.g. zero padding or a collapsed output.
+const WEAK_KEY_RUN: usize = 16;
+
+/// Checks that a freshly derived key does not contain an improbably long run of the same byte.
+///
+/// # Arguments
+///
+/// * `key` - The derived key.
+///
+/// # Returns
+///
+/// `Ok(())`, or `SystemTrayError` code 19 if the key is weak.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::check_key_strength;
+/// # use secrecy::ExposeSecret;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let derived_key = gene3(b"my_password")?;
+/// check_key_strength(derived_key.expose_secret())?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn check_key_strength(key: &[u8]) -> Result<(), SystemTrayError> {
+    let mut run = 0;
+    for (i, byte) in key.iter().enumerate() {
+        run = if i > 0 && key[i - 1] == *byte { run + 1 } else { 1 };
+        if run >= WEAK_KEY_RUN {
+            return Err(SystemTrayError::new(19));
+        }
+    }
+
+    Ok(())
+}
+
+/// Derives `count` round keys from a master key.
+///
+/// Round key `i` is `derive_subkey(master, &i.to_be_bytes())` with `i` a `u32`, so the keys are domain separated
+/// and each one costs a single BLAKE3 pass instead of a `gene3` or `kdfwagen` call. The keys are derived in parallel.
+///
+/// # Arguments
+///
+/// * `master` - The master key.
+/// * `count` - The number of round keys.
+///
+/// # Returns
+///
+/// The `count` round keys, in round order.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::derive_round_keys;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let master = gene3(b"my_password")?;
+/// let round_keys = derive_round_keys(&master, 8);
+/// # Ok(())
+/// # }
+/// ```
+pub fn derive_round_keys(master: &(impl KeyMaterial + Sync), count: u32) -> Vec<Secret<Vec<u8>>> {
+    (0..count)
+        .into_par_iter()
+        .map(|round| derive_round_key(master, round))
+        .collect()
+}
+
+/// Derives the key of a single round, the same as element `round` of `derive_round_keys`.
+///
+/// # Arguments
+///
+/// * `master` - The master key.
+/// * `round` - The index of the round.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::derive_round_key;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let master = gene3(b"my_password")?;
+/// let third_round_key = derive_round_key(&master, 2);
+/// # Ok(())
+/// # }
+/// ```
+pub fn derive_round_key(master: &impl KeyMaterial, round: u32) -> Secret<Vec<u8>> {
+    derive_subkey(master, &round.to_be_bytes())
+}
+
+/// Memoizes `gene3` and `kdfwagen` derivations for a limited time, for applications deriving the same key on every request.
+///
+/// Nothing is cached unless a `KeyCache` is created and used instead of the free functions. Each cached key stays in
+/// memory until its time to live is over, and is zeroized when it is evicted or the cache is dropped.
+/// Entries are found by a keyed BLAKE3 hash of the inputs, with a random key per cache, so the passwords are not stored.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// # use horizon::keys::{KeyCache, TableKey};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let password = "user password";
+/// let cache = KeyCache::new(Duration::from_secs(300))?;
+///
+/// // Argon2 only runs on the first request of each user within 5 minutes
+/// let key1 = TableKey::new(cache.gene3(password.as_bytes())?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct KeyCache {
+    ttl: Duration,
+    hash_key: [u8; 32],
+    entries: Mutex<HashMap<[u8; 32], CachedKey>>,
+}
+
+/// A cached key and the time it was derived, zeroized when evicted.
+///
+/// The cache stores the key bytes; the tests store a type recording what is left of them once zeroized.
+struct CachedKey<K: Zeroize = Vec<u8>> {
+    derived_at: Instant,
+    key: K,
+}
+
+impl<K: Zeroize> Drop for CachedKey<K> {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl KeyCache {
+    /// Creates an empty cache keeping each key for `ttl`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `secured_seed` or of the generator, which key the hash of the entries.
+    pub fn new(ttl: Duration) -> Result<Self, SystemTrayError> {
+        let mut hash_key = [0u8; 32];
+        Nebula::new(secured_seed()?).try_fill_bytes(&mut hash_key).map_err(|err| {
+            // Nebula wraps its own error, hand it back as is
+            match err.take_inner().downcast::<SystemTrayError>() {
+                Ok(err) => *err,
+                Err(_) => SystemTrayError::new(12),
+            }
+        })?;
+
+        Ok(KeyCache { ttl, hash_key, entries: Mutex::new(HashMap::new()) })
+    }
+
+    /// Returns `gene3(seed)`, from the cache if it was derived less than `ttl` ago.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `gene3`, which is not cached.
+    pub fn gene3(&self, seed: &[u8]) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+        let entry = self.entry_id(&[b"gene3", get_salt().as_bytes(), seed]);
+
+        self.get_or_derive(entry, || gene3(seed))
+    }
+
+    /// Returns `kdfwagen_with_length(password, salt, iterations, length)`, from the cache if it was derived less than `ttl` ago.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `kdfwagen_with_length`, which is not cached.
+    pub fn kdfwagen(&self, password: &[u8], salt: &[u8], iterations: usize, length: usize) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+        let params = [(iterations as u64).to_be_bytes(), (length as u64).to_be_bytes()].concat();
+        let entry = self.entry_id(&[b"kdfwagen", salt, &params, password]);
+
+        self.get_or_derive(entry, || kdfwagen_with_length(password, salt, iterations, length))
+    }
+
+    /// Evicts the keys whose time to live is over.
+    pub fn purge_expired(&self) {
+        let ttl = self.ttl;
+        self.entries.lock().unwrap().retain(|_, entry| entry.derived_at.elapsed() < ttl);
+    }
+
+    /// Evicts every key.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Returns the number of cached keys, expired ones included until they are evicted.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no key is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hashes the length-prefixed inputs of a derivation into the identifier of its entry.
+    fn entry_id(&self, inputs: &[&[u8]]) -> [u8; 32] {
+        let mut hasher = Hasher::new_keyed(&self.hash_key);
+        for input in inputs {
+            hasher.update(&(input.len() as u64).to_be_bytes());
+            hasher.update(input);
+        }
+
+        *hasher.finalize().as_bytes()
+    }
+
+    fn get_or_derive<E>(&self, entry: [u8; 32], derive: impl FnOnce() -> Result<Secret<Vec<u8>>, E>) -> Result<Secret<Vec<u8>>, E> {
+        self.purge_expired();
+        if let Some(cached) = self.entries.lock().unwrap().get(&entry) {
+            return Ok(Secret::new(cached.key.clone()));
+        }
+
+        // Derive without holding the lock, so other keys can be served meanwhile
+        let key = derive()?;
+        let cached = CachedKey { derived_at: Instant::now(), key: key.expose_secret().clone() };
+        self.entries.lock().unwrap().insert(entry, cached);
+
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::cryptex::{decrypt_file, decrypt_file_ref, encrypt_file, encrypt_file_ref};
+    use crate::gene3;
+    use crate::log::{LogReader, LogWriter};
+
+    use super::*;
+
+    /// Key bytes recording what is left of them in `left` once zeroized.
+    struct ObservedKey {
+        bytes: Vec<u8>,
+        left: Rc<RefCell<Vec<Vec<u8>>>>,
+    }
+
+    impl Zeroize for ObservedKey {
+        fn zeroize(&mut self) {
+            self.bytes.as_mut_slice().zeroize();
+            self.left.borrow_mut().push(self.bytes.clone());
+        }
+    }
+
+    /// Key material held outside of a `Secret`, as a handle on an external key store would be.
+    struct BorrowedKey<'a>(&'a [u8]);
+
+    impl KeyMaterial for BorrowedKey<'_> {
+        fn expose(&self) -> &[u8] {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_custom_key_material() {
+        let table_bytes = gene3(b"material_key_one").unwrap();
+        let row_bytes = gene3(b"material_key_two").unwrap();
+        let plain_text = b"Encrypted with keys that never were a Secret".to_vec();
+
+        let key1 = TableKey::new(BorrowedKey(table_bytes.expose_secret()));
+        let key2 = RowKey::new(BorrowedKey(row_bytes.expose_secret()));
+        let encrypted = encrypt_file_ref(&plain_text, &key1, &key2).unwrap();
+
+        assert_eq!(decrypt_file_ref(&encrypted, &key1, &key2).unwrap(), plain_text);
+
+        let secret_key1 = TableKey::new(Secret::new(table_bytes.expose_secret().clone()));
+        let secret_key2 = RowKey::new(Secret::new(row_bytes.expose_secret().clone()));
+        assert_eq!(encrypt_file_ref(&plain_text, &secret_key1, &secret_key2).unwrap(), encrypted);
+
+        let mut log = LogWriter::new(Vec::new(), &key1, &key2);
+        log.append(&plain_text).unwrap();
+        let records: Vec<Vec<u8>> = LogReader::new(&log.into_inner()[..], &key1, &key2).map(Result::unwrap).collect();
+        assert_eq!(records, vec![plain_text]);
+    }
+
+    #[test]
+    fn test_derive_subkey() {
+        let master = Secret::new(vec![7u8; KEY_LENGTH]);
+
+        let table_key = TableKey::derive(&master);
+        let row_key = RowKey::derive(&master);
+
+        assert_eq!(table_key.expose_secret().len(), KEY_LENGTH);
+        assert_ne!(table_key.expose_secret(), row_key.expose_secret());
+        assert_ne!(table_key.expose_secret(), master.expose_secret());
+        assert_eq!(TableKey::derive(&master).expose_secret(), table_key.expose_secret());
+    }
+
+    #[test]
+    fn test_key_roles() {
+        let plain_text = b"the roles of the keys are checked by the compiler".to_vec();
+        let table_key = TableKey::new(gene3(b"table_password").unwrap());
+        let row_key = RowKey::new(gene3(b"row_password").unwrap());
+
+        let cipher_text = encrypt_file(plain_text.clone(), &table_key, &row_key).unwrap();
+        assert_eq!(decrypt_file(cipher_text.clone(), &table_key, &row_key).unwrap(), plain_text);
+
+        // Swapping the keys now has to be spelled out, and it does not decrypt
+        let swapped_table_key = TableKey::new(row_key.into_secret());
+        let swapped_row_key = RowKey::new(table_key.into_secret());
+        assert_ne!(decrypt_file(cipher_text, &swapped_table_key, &swapped_row_key).unwrap(), plain_text);
+    }
+
+    #[test]
+    fn test_derive_round_keys() {
+        let master = Secret::new(vec![3u8; KEY_LENGTH]);
+
+        let round_keys = derive_round_keys(&master, 16);
+
+        assert_eq!(round_keys.len(), 16);
+        for (i, round_key) in round_keys.iter().enumerate() {
+            assert_eq!(round_key.expose_secret(), derive_subkey(&master, &(i as u32).to_be_bytes()).expose_secret());
+            assert_eq!(round_key.expose_secret(), derive_round_key(&master, i as u32).expose_secret());
+            for other in &round_keys[i + 1..] {
+                assert_ne!(round_key.expose_secret(), other.expose_secret());
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_key_strength() {
+        let key = gene3(b"strong_password").unwrap();
+        assert!(check_key_strength(key.expose_secret()).is_ok());
+
+        // What a length-capped KDF used to return: the tail of the key left as zero padding
+        let mut padded = key.expose_secret()[..KEY_LENGTH / 2].to_vec();
+        padded.resize(KEY_LENGTH, 0);
+        assert!(check_key_strength(&padded).is_err());
+
+        assert!(check_key_strength(&[0xAA; WEAK_KEY_RUN]).is_err());
+        assert!(check_key_strength(&[0xAA; WEAK_KEY_RUN - 1]).is_ok());
+    }
+
+    #[test]
+    fn test_key_cache() {
+        let cache = KeyCache::new(Duration::from_secs(60)).unwrap();
+        assert!(cache.is_empty());
+
+        let first = cache.gene3(b"cached_password").unwrap();
+        let second = cache.gene3(b"cached_password").unwrap();
+
+        assert_eq!(first.expose_secret(), second.expose_secret());
+        assert_eq!(first.expose_secret(), gene3(b"cached_password").unwrap().expose_secret());
+        assert_eq!(cache.len(), 1);
+
+        let mut derivations = 0;
+        let entry = cache.entry_id(&[b"counted derivation"]);
+        for _ in 0..3 {
+            cache.get_or_derive(entry, || {
+                derivations += 1;
+                Ok::<_, SystemTrayError>(Secret::new(vec![7; 64]))
+            }).unwrap();
+        }
+        assert_eq!(derivations, 1, "A derivation within the time to live was not served from the cache");
+        assert_eq!(cache.len(), 2);
+
+        let key = cache.kdfwagen(b"password", b"some salt", 10, 64).unwrap();
+        assert_eq!(key.expose_secret(), kdfwagen_with_length(b"password", b"some salt", 10, 64).unwrap().expose_secret());
+        assert_ne!(cache.kdfwagen(b"password", b"some salt", 11, 64).unwrap().expose_secret(), key.expose_secret());
+        assert_eq!(cache.len(), 4);
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_key_cache_expiry() {
+        let cache = KeyCache::new(Duration::from_millis(50)).unwrap();
+        cache.kdfwagen(b"password", b"some salt", 10, 64).unwrap();
+        assert_eq!(cache.len(), 1);
+
+        std::thread::sleep(Duration::from_millis(60));
+        cache.purge_expired();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_cached_key_zeroized_when_dropped() {
+        let left = Rc::new(RefCell::new(Vec::new()));
+        let mut entries = HashMap::new();
+        for (id, byte) in [(1u8, 0xAA), (2, 0xBB)] {
+            let key = ObservedKey { bytes: vec![byte; 64], left: Rc::clone(&left) };
+            entries.insert(id, CachedKey { derived_at: Instant::now(), key });
+        }
+
+        // Evicted like `purge_expired` and `clear` do
+        entries.retain(|&id, _| id != 1);
+        assert_eq!(left.borrow().len(), 1);
+        entries.clear();
+
+        let left = left.borrow();
+        assert_eq!(left.len(), 2);
+        assert!(left.iter().all(|key| key.len() == 64 && key.iter().all(|&byte| byte == 0)), "An evicted key was not zeroized");
+    }
+}
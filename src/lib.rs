@@ -0,0 +1,1552 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use argon2::{Algorithm, Argon2, Params, Version};
+
+use rayon::prelude::*;
+use secrecy::{ExposeSecret, Secret};
+use sysinfo::System;
+
+use crate::cryptex::{check_keys, CipherTables};
+use crate::keys::{check_key_strength, KeyMaterial, RowKey, TableKey};
+use crate::nebula::{Nebula, secured_seed, XofRng};
+use crate::systemtrayerror::SystemTrayError;
+
+pub mod systemtrayerror;
+pub mod kdfwagen;
+pub mod cryptex;
+pub mod nebula;
+pub mod archive;
+pub mod format;
+pub mod log;
+pub mod keys;
+pub mod shares;
+pub mod rounds;
+pub mod transform;
+pub mod text;
+pub mod shred;
+#[cfg(feature = "serde")]
+pub mod value;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+
+/// Internal primitives exposed for benchmarks in other crates, with the `bench-internals` feature.
+///
+/// This module is unstable: it is not part of the public API and can change or disappear in any release.
+#[cfg(feature = "bench-internals")]
+#[doc(hidden)]
+pub mod __private {
+    use secrecy::Secret;
+
+    use crate::systemtrayerror::SystemTrayError;
+
+    pub use crate::nebula::{seeded_shuffle, unbiased_shuffle};
+
+    /// The 3D reference table the cipher tables are checked against.
+    pub fn table3(size: usize, seed: u64) -> Vec<Vec<Vec<u8>>> {
+        crate::table3(size, seed)
+    }
+
+    /// The HMAC-BLAKE3 key derivation, for `KEY_LENGTH` bytes.
+    pub fn kdfwagen(password: &[u8], salt: &[u8], iterations: usize) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+        crate::kdfwagen::kdfwagen(password, salt, iterations)
+    }
+}
+
+const KEY_LENGTH: usize = 512;
+
+/// Minimum length in bytes of the machine salt used by `generate_key2`.
+pub const MIN_SALT_LENGTH: usize = 10;
+
+/// Default input size in bytes from which the byte-wise stages run in parallel.
+///
+/// The `parallel_threshold` benchmark (`cargo bench --bench cipher -- parallel_threshold`) measures the parallel path
+/// at about 11 µs of pool dispatch plus up to 1.8 times the serial work per byte, the serial path at about 5.5 ns per byte.
+/// Split over two cores, the parallel path wins from about 16 KiB; more cores lower that point, and a single core never
+/// wins, which `set_parallel_threshold(usize::MAX)` accounts for.
+const DEFAULT_PARALLEL_THRESHOLD: usize = 16 * 1024;
+
+static PARALLEL_THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_PARALLEL_THRESHOLD);
+
+#[cfg(test)]
+thread_local! {
+    /// Parallel threshold used instead of `PARALLEL_THRESHOLD` on the current test thread.
+    static PARALLEL_THRESHOLD_OVERRIDE: std::cell::Cell<Option<usize>> = const { std::cell::Cell::new(None) };
+}
+
+/// Number of hash bytes shown in a key fingerprint.
+const FINGERPRINT_LENGTH: usize = 16;
+
+/// Sets the input size in bytes from which the byte-wise stages of the cipher run in parallel.
+///
+/// Dispatching small inputs to the thread pool costs more than processing them, so inputs shorter
+/// than `threshold` are processed on the calling thread. The output is the same either way.
+///
+/// The threshold is process-wide, as the crate has no configuration struct to hold it: it applies to every
+/// cipher function, including calls already running on other threads.
+///
+/// # Arguments
+///
+/// * `threshold` - The minimum input length processed in parallel, 0 to always run in parallel.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::set_parallel_threshold;
+/// // Many short messages on a busy server: keep them off the thread pool
+/// set_parallel_threshold(1 << 20);
+/// ```
+pub fn set_parallel_threshold(threshold: usize) {
+    PARALLEL_THRESHOLD.store(threshold, Ordering::Relaxed);
+}
+
+/// Returns `true` if an input of `len` bytes is below the parallel threshold.
+///
+/// In tests, the threshold set by `set_parallel_threshold_override` on the current thread takes precedence,
+/// so a test does not change the threshold of the others.
+fn runs_serially(len: usize) -> bool {
+    #[cfg(test)]
+    if let Some(threshold) = PARALLEL_THRESHOLD_OVERRIDE.with(|threshold| threshold.get()) {
+        return len < threshold;
+    }
+
+    len < PARALLEL_THRESHOLD.load(Ordering::Relaxed)
+}
+
+/// Replaces the parallel threshold on the current test thread, or restores the global one with `None`.
+#[cfg(test)]
+fn set_parallel_threshold_override(threshold: Option<usize>) {
+    PARALLEL_THRESHOLD_OVERRIDE.with(|threshold_override| threshold_override.set(threshold));
+}
+
+/// Maps every byte of `input` with its position, in parallel if `input` reaches the parallel threshold.
+pub(crate) fn map_indexed<T: Send>(input: &[u8], f: impl Fn(usize, u8) -> T + Sync + Send) -> Vec<T> {
+    if runs_serially(input.len()) {
+        input.iter().enumerate().map(|(i, &c)| f(i, c)).collect()
+    } else {
+        input.par_iter().enumerate().map(|(i, &c)| f(i, c)).collect()
+    }
+}
+
+/// Generates a 3-dimensional table of bytes.
+///
+/// # Arguments
+///
+/// * `size` - The size of each dimension of the table.
+/// * `seed` - The seed value for shuffling the characters.
+///
+/// # Returns
+///
+/// A 3-dimensional vector containing bytes.
+///
+/// # Panics
+///
+/// This function will panic if `size` is 0.
+///
+/// # Examples
+///
+/// ```ignore
+/// let size = 10;
+/// let seed = 42;
+/// let table = table3(size, seed);
+/// assert_eq!(table.len(), size);
+/// assert_eq!(table[0].len(), size);
+/// assert_eq!(table[0][0].len(), size);
+/// ```
+///
+/// The cipher itself uses the equivalent `CipherTables`, this full table is kept as the reference they are checked against.
+#[cfg(any(test, feature = "bench-internals"))]
+fn table3(size: usize, seed: u64) -> Vec<Vec<Vec<u8>>> {
+    let mut characters: Vec<u8> = (0..=255).collect();
+
+    nebula::seeded_shuffle(&mut characters, seed as usize);
+
+    (0..size).into_par_iter().chunks(1000).map(|i_chunk| {
+        i_chunk.into_par_iter().map(|i| {
+            (0..size).into_par_iter().chunks(1000).map(|j_chunk| {
+                j_chunk.into_par_iter().map(|j: usize| {
+                    (0..size).map(|k| {
+                        let idx: usize = (i + j + k) % size;
+                        characters[idx]
+                    }).collect::<Vec<u8>>()
+                }).collect::<Vec<Vec<u8>>>()
+            }).flatten().collect::<Vec<Vec<u8>>>()
+        }).collect::<Vec<Vec<Vec<u8>>>>()
+    }).flatten().collect::<Vec<Vec<Vec<u8>>>>()
+}
+
+
+/// Generates a 3-dimensional table whose rows are independent keyed permutations.
+///
+/// In `table3`, every row is a rotation of the same shuffled alphabet, so neighbouring rows are closely related.
+/// Here each row is shuffled on its own from `seed` and its coordinates, so knowing a row tells nothing about the others.
+///
+/// # Arguments
+///
+/// * `size` - The size of each dimension of the table, at most 256.
+/// * `seed` - The seed value the rows are derived from.
+///
+/// # Returns
+///
+/// A 3-dimensional vector whose rows are permutations of the bytes below `size`.
+///
+/// # Panics
+///
+/// Panics if `size` is 0 or greater than 256, as the rows could not be permutations of distinct bytes.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::table3_keyed;
+/// let table = table3_keyed(256, 42);
+/// assert_ne!(table[0][0], table[0][1]);
+/// ```
+pub fn table3_keyed(size: usize, seed: u64) -> Vec<Vec<Vec<u8>>> {
+    assert!((1..=256).contains(&size), "size must be between 1 and 256");
+
+    (0..size).into_par_iter().map(|i| {
+        (0..size).map(|j| keyed_row(seed, i, j, size)).collect()
+    }).collect()
+}
+
+/// Shuffles the bytes below `size` with a generator derived from `seed` and the row coordinates.
+pub(crate) fn keyed_row(seed: u64, table_2d: usize, row: usize, size: usize) -> Vec<u8> {
+    let mut material = Vec::with_capacity(24);
+    material.extend_from_slice(&seed.to_be_bytes());
+    material.extend_from_slice(&(table_2d as u64).to_be_bytes());
+    material.extend_from_slice(&(row as u64).to_be_bytes());
+
+    let mut characters: Vec<u8> = (0..size).map(|c| c as u8).collect();
+    nebula::unbiased_shuffle(&mut characters, &mut XofRng::new(&material));
+    characters
+}
+
+/// Fills `out` with the BLAKE3 extendable output of `input`, however long `out` is.
+///
+/// The output length always comes from the buffer, and shorter outputs are prefixes of longer ones.
+pub(crate) fn xof(input: &[u8], out: &mut [u8]) {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(input);
+    hasher.finalize_xof().fill(out);
+}
+
+/// Checks that a salt has at least `MIN_SALT_LENGTH` bytes.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 10, with the minimum and actual lengths in its message, if the salt is too short.
+fn check_salt(salt: &[u8]) -> Result<(), SystemTrayError> {
+    if salt.len() < MIN_SALT_LENGTH {
+        return Err(SystemTrayError::salt_too_short(MIN_SALT_LENGTH, salt.len()));
+    }
+
+    Ok(())
+}
+
+/// Generates a unique salt string based on system information.
+///
+/// # Returns
+///
+/// A string containing a unique salt based on system information.
+///
+/// # Panics
+///
+/// This function will panic if any of the system information queries fail.
+///
+/// # Examples
+///
+/// ```ignore
+/// let salt = get_salt();
+/// println!("Generated salt: {}", salt);
+/// ```
+fn get_salt() -> String {
+    System::name().unwrap_or("".to_string()) + &System::host_name().unwrap_or("".to_string()) + &System::os_version().unwrap_or("".to_string())  + &System::kernel_version().unwrap_or("".to_string())
+}
+
+
+
+/// Calculates the sum of the elements in a byte slice representing a MAC address.
+///
+/// # Arguments
+///
+/// * `adresse_mac` - A reference to a byte slice representing a MAC address.
+///
+/// # Returns
+///
+/// The sum of the elements in the byte slice as a `u64` value, 0 for an empty slice.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mac_address: [u8; 6] = [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF];
+/// let sum = addition_chiffres(&mac_address);
+/// assert_eq!(sum, 0xAABBCCDDEEFF);
+/// ```
+fn addition_chiffres(adresse_mac: &[u8]) -> u64 {
+    adresse_mac.par_iter().map(|&x| x as u64).sum()
+}
+
+/// Computes the seed the cipher tables are derived from for a pair of keys.
+///
+/// The seed is the product of the byte sums of the two keys, computed without overflow; the cipher uses it truncated to 64 bits.
+/// Since the product is commutative and the sums ignore byte order, swapping the keys, or reordering their bytes,
+/// gives the same seed and therefore the same tables.
+///
+/// # Arguments
+///
+/// * `key1` - The table key.
+/// * `key2` - The row key.
+///
+/// # Returns
+///
+/// The seed of the cipher tables.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::derive_seed;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let seed = derive_seed(&TableKey::new(gene3(b"lib key one")?), &RowKey::new(gene3(b"lib key two")?));
+/// let swapped = derive_seed(&TableKey::new(gene3(b"lib key two")?), &RowKey::new(gene3(b"lib key one")?));
+/// assert_eq!(seed, swapped);
+/// # Ok(())
+/// # }
+/// ```
+pub fn derive_seed(key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> u128 {
+    addition_chiffres(key1.expose()) as u128 * addition_chiffres(key2.expose()) as u128
+}
+
+/// Computes the full-width seed of `FormatVersion::V2` from a BLAKE3 hash of both keys.
+///
+/// Unlike `derive_seed`, every byte of both keys and their order change the seed. The cipher tables use its upper 64 bits.
+///
+/// # Arguments
+///
+/// * `key1` - The table key.
+/// * `key2` - The row key.
+///
+/// # Returns
+///
+/// The first 16 bytes of the hash, big-endian.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::derive_seed_v2;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let seed = derive_seed_v2(&TableKey::new(gene3(b"lib key one")?), &RowKey::new(gene3(b"lib key two")?));
+/// let swapped = derive_seed_v2(&TableKey::new(gene3(b"lib key two")?), &RowKey::new(gene3(b"lib key one")?));
+/// assert_ne!(seed, swapped);
+/// # Ok(())
+/// # }
+/// ```
+pub fn derive_seed_v2(key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> u128 {
+    seed_v2(key1.expose(), key2.expose())
+}
+
+/// Hashes the keys for `derive_seed_v2`, prefixing the length of `key1` so the boundary between the keys is unambiguous.
+pub(crate) fn seed_v2(key1: &[u8], key2: &[u8]) -> u128 {
+    let mut hasher = blake3::Hasher::new_derive_key("horizon table seed v2");
+    hasher.update(&(key1.len() as u64).to_be_bytes());
+    hasher.update(key1);
+    hasher.update(key2);
+
+    u128::from_be_bytes(hasher.finalize().as_bytes()[..16].try_into().unwrap())
+}
+
+/// Generates a key based on a seed string.
+///
+/// # Arguments
+///
+/// * `seed` - A reference to a seed string used for key generation.
+///
+/// # Returns
+///
+/// A result containing either the generated key as a `Vec<u8>` or a `SystemTrayError`.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::generate_key2;
+/// # use secrecy::ExposeSecret;
+/// let seed = "random_seed_string";
+/// match generate_key2(seed) {
+///     Ok(key) => println!("Generated a key of {} bytes", key.expose_secret().len()),
+///     Err(err) => eprintln!("Error: {}", err),
+/// }
+/// ```
+pub fn generate_key2(seed: &str) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+    if seed.len() < 10 {
+        return Err(SystemTrayError::new(4));
+    }
+
+    let salt = get_salt();
+    check_salt(salt.as_bytes())?;
+
+    derive_key(seed.as_bytes(), salt.as_ref())
+}
+
+/// The Argon2 algorithm and version used to derive a key, see `gene3_with`.
+///
+/// The default, Argon2id version 0x13, is the one used by `gene3`. Nothing records the variant next to the
+/// cipher text: callers that pick another one store `to_byte` with their data and read it back with `from_byte`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KdfVariant {
+    pub algorithm: Argon2Algorithm,
+    pub version: Argon2Version,
+}
+
+/// The Argon2 algorithms, see RFC 9106.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Argon2Algorithm {
+    /// Data-dependent memory access, faster but open to side-channel attacks.
+    Argon2d,
+    /// Data-independent memory access, required by some compliance regimes.
+    Argon2i,
+    /// Argon2i for the first pass, then Argon2d.
+    #[default]
+    Argon2id,
+}
+
+/// The versions of the Argon2 algorithms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Argon2Version {
+    /// Version 16, the original release.
+    V0x10,
+    /// Version 19, the current one.
+    #[default]
+    V0x13,
+}
+
+impl KdfVariant {
+    /// Encodes the variant as one byte: the algorithm in the low nibble, the version in the high one.
+    pub fn to_byte(self) -> u8 {
+        let algorithm = match self.algorithm {
+            Argon2Algorithm::Argon2d => 0,
+            Argon2Algorithm::Argon2i => 1,
+            Argon2Algorithm::Argon2id => 2,
+        };
+        let version = match self.version {
+            Argon2Version::V0x10 => 0,
+            Argon2Version::V0x13 => 1,
+        };
+
+        version << 4 | algorithm
+    }
+
+    /// Decodes a byte written by `to_byte`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SystemTrayError` code 25 if the byte does not encode a known algorithm and version.
+    pub fn from_byte(byte: u8) -> Result<Self, SystemTrayError> {
+        let algorithm = match byte & 0x0F {
+            0 => Argon2Algorithm::Argon2d,
+            1 => Argon2Algorithm::Argon2i,
+            2 => Argon2Algorithm::Argon2id,
+            _ => return Err(SystemTrayError::new(25)),
+        };
+        let version = match byte >> 4 {
+            0 => Argon2Version::V0x10,
+            1 => Argon2Version::V0x13,
+            _ => return Err(SystemTrayError::new(25)),
+        };
+
+        Ok(KdfVariant { algorithm, version })
+    }
+
+    fn argon2(self, params: Params) -> Argon2<'static> {
+        let algorithm = match self.algorithm {
+            Argon2Algorithm::Argon2d => Algorithm::Argon2d,
+            Argon2Algorithm::Argon2i => Algorithm::Argon2i,
+            Argon2Algorithm::Argon2id => Algorithm::Argon2id,
+        };
+        let version = match self.version {
+            Argon2Version::V0x10 => Version::V0x10,
+            Argon2Version::V0x13 => Version::V0x13,
+        };
+
+        Argon2::new(algorithm, version, params)
+    }
+}
+
+/// Derives a key from a seed with Argon2 and the machine salt.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 17 if Argon2 rejects the machine salt, e.g. when the system does not report
+/// its name and versions, and code 19 if the key fails `check_key_strength`.
+pub fn gene3(seed: &[u8]) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+    gene3_with(seed, KdfVariant::default())
+}
+
+/// Derives a key from a seed and the machine salt like `gene3`, with the given Argon2 algorithm and version.
+///
+/// Keys derived with different variants are unrelated, so the variant has to be kept to derive the same key again.
+///
+/// # Arguments
+///
+/// * `seed` - The seed of the key, such as a password.
+/// * `variant` - The Argon2 algorithm and version.
+///
+/// # Errors
+///
+/// Returns the errors of `gene3`.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::{gene3_with, Argon2Algorithm, Argon2Version, KdfVariant};
+/// # use horizon::keys::TableKey;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let variant = KdfVariant { algorithm: Argon2Algorithm::Argon2i, version: Argon2Version::V0x13 };
+/// let key1 = TableKey::new(gene3_with(b"my password", variant)?);
+///
+/// // Kept with the cipher text to derive the key again
+/// let stored = variant.to_byte();
+/// # Ok(())
+/// # }
+/// ```
+pub fn gene3_with(seed: &[u8], variant: KdfVariant) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+    derive_key_with(seed, get_salt().as_ref(), Params::default(), variant)
+}
+
+/// Derives a `KEY_LENGTH` bytes key from a seed and a salt with Argon2, returning code 17 if Argon2 fails
+/// and code 19 if the key fails `check_key_strength`.
+fn derive_key(seed: &[u8], salt: &[u8]) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+    derive_key_with_params(seed, salt, Params::default())
+}
+
+/// Derives a `KEY_LENGTH` bytes key with Argon2id using explicit parameters, so the derivation can be pinned by known-answer tests.
+fn derive_key_with_params(seed: &[u8], salt: &[u8], params: Params) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+    derive_key_with(seed, salt, params, KdfVariant::default())
+}
+
+/// Derives a `KEY_LENGTH` bytes key with explicit parameters and Argon2 variant.
+fn derive_key_with(seed: &[u8], salt: &[u8], params: Params, variant: KdfVariant) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+    let mut output_key_material = vec![0u8; KEY_LENGTH];
+
+    variant.argon2(params)
+        .hash_password_into(seed, salt, &mut output_key_material)
+        .map_err(|_| SystemTrayError::new(17))?;
+    check_key_strength(&output_key_material)?;
+
+    // Wrap the output key material in a Secret and return it
+    Ok(Secret::new(output_key_material))
+}
+
+/// Derives one key per `(password, salt)` pair with Argon2, running the derivations in parallel.
+///
+/// Each Argon2 derivation allocates its own memory, so at most `max_parallel` of them run at the same time.
+///
+/// # Arguments
+///
+/// * `inputs` - The password and salt of each key.
+/// * `max_parallel` - The maximum number of derivations running at the same time, or 0 for one per core.
+///
+/// # Returns
+///
+/// The keys, in the order of `inputs`, or `SystemTrayError` code 17 if a derivation fails and code 30 if the threads
+/// running the derivations cannot be started.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::derive_many;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # struct User { password: Vec<u8>, salt: Vec<u8> }
+/// # let users = vec![User { password: b"first password".to_vec(), salt: b"first user salt".to_vec() }];
+/// let inputs: Vec<(&[u8], &[u8])> = users.iter().map(|user| (user.password.as_ref(), user.salt.as_ref())).collect();
+/// let keys = derive_many(&inputs, 4)?;
+/// # assert_eq!(keys.len(), users.len());
+/// # Ok(())
+/// # }
+/// ```
+pub fn derive_many(inputs: &[(&[u8], &[u8])], max_parallel: usize) -> Result<Vec<Secret<Vec<u8>>>, SystemTrayError> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(max_parallel)
+        .build()
+        .map_err(|_| SystemTrayError::new(30))?;
+
+    pool.install(|| {
+        inputs
+            .par_iter()
+            .map(|&(password, salt)| derive_key(password, salt))
+            .collect()
+    })
+}
+
+/// Computes a short fingerprint identifying a key without revealing it.
+///
+/// # Arguments
+///
+/// * `key` - A reference to the secret key to identify.
+///
+/// # Returns
+///
+/// The first 16 bytes of a domain-separated BLAKE3 hash of the key, as colon-separated hex pairs.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::{gene3, key_fingerprint};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let key = gene3(b"my_password")?;
+/// println!("Key fingerprint: {}", key_fingerprint(&key)); // e.g. "3f:a0:...:7c"
+/// # Ok(())
+/// # }
+/// ```
+pub fn key_fingerprint(key: &Secret<Vec<u8>>) -> String {
+    let mut hasher = blake3::Hasher::new_derive_key("horizon key fingerprint");
+    hasher.update(key.expose_secret());
+    let hash = hasher.finalize();
+
+    hash.as_bytes()[..FINGERPRINT_LENGTH]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+
+/// Compares two byte slices, taking the same time wherever they differ.
+///
+/// Only the length leaks: slices of different lengths are rejected at once.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    // Look at every byte whatever the first difference, so the timing does not tell where it is
+    a.iter().zip(b).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
+}
+
+/// Checks a password against the fingerprint of the key it should derive, without exposing the key.
+///
+/// The key is derived with Argon2 like `generate_key2`, but with the given salt, then its `key_fingerprint`
+/// is compared to `fingerprint` in constant time.
+///
+/// # Arguments
+///
+/// * `password` - The password to check.
+/// * `salt` - The salt the stored key was derived with.
+/// * `fingerprint` - The stored fingerprint of the key.
+///
+/// # Returns
+///
+/// `true` if the password derives a key with the same fingerprint, `false` otherwise or if the derivation fails.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::{derive_many, key_fingerprint, verify_password};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # struct User { salt: Vec<u8>, key_fingerprint: String }
+/// # let typed_password = "typed password".to_string();
+/// # let salt = b"per-user salt".to_vec();
+/// # let key = derive_many(&[(typed_password.as_bytes(), &salt)], 1)?.remove(0);
+/// # let user = User { key_fingerprint: key_fingerprint(&key), salt };
+/// if verify_password(&typed_password, &user.salt, &user.key_fingerprint) {
+///     println!("Welcome back");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn verify_password(password: &str, salt: &[u8], fingerprint: &str) -> bool {
+    let key = match derive_key(password.as_bytes(), salt) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+
+    constant_time_eq(key_fingerprint(&key).as_bytes(), fingerprint.as_bytes())
+}
+
+/// Inserts random stars into a byte vector.
+///
+/// # Arguments
+///
+/// * `word` - A byte vector into which random stars will be inserted.
+///
+/// # Returns
+///
+/// A byte vector with random stars inserted, or the error of `secured_seed` or of the generator.
+///
+/// # Examples
+///
+/// ```ignore
+/// let word = b"example".to_vec();
+/// let word_with_stars = insert_random_stars(word)?;
+/// println!("Word with stars: {:?}", word_with_stars);
+/// ```
+fn insert_random_stars(mut word: Vec<u8>) -> Result<Vec<u8>, SystemTrayError> {
+    let rng = Arc::new(Mutex::new(Nebula::new(secured_seed()?)));
+
+    let num_stars: usize = rng.lock().unwrap().generate_bounded_number((word.len()/2) as u128, word.len() as u128)? as usize;
+
+    let mut stars: Vec<u8> = vec![0; num_stars];
+
+    let random_indices: Vec<usize> = (0..num_stars).into_par_iter()
+        .map(|_| {
+            let mut rng = rng.lock().unwrap();
+            rng.generate_bounded_number(0, word.len() as u128).map(|index| index as usize)
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut sorted_indices = random_indices;
+    sorted_indices.par_sort_unstable_by(|a, b| b.cmp(a));
+
+    for index in sorted_indices {
+        word.insert(index, stars.pop().unwrap());
+    }
+
+    Ok(word)
+}
+
+/// Creates a vector based on arithmetic operations and a seed.
+///
+/// # Arguments
+///
+/// * `val1` - The first value used for arithmetic operations.
+/// * `val2` - The second value used for arithmetic operations.
+/// * `seed` - The seed value used for vector generation.
+///
+/// # Returns
+///
+/// A vector of bytes generated based on arithmetic operations and the seed.
+///
+/// # Examples
+///
+/// ```ignore
+/// let val1 = 10;
+/// let val2 = 20;
+/// let seed = 42;
+/// let result = vz_maker(val1, val2, seed);
+/// println!("Resulting vector: {:?}", result);
+/// ```
+fn vz_maker(val1: u64, val2:u64, seed: u64) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+    gene3(&[(val1+val2) as u8,val1.checked_rem(val2).unwrap_or(0) as u8, seed as u8, val1.abs_diff(val2) as u8,  val1.wrapping_mul(val2) as u8])
+}
+
+
+/// Encrypts plain text using a double-key encryption scheme.
+///
+/// This is not `encrypt_file` with extra steps: besides the random stars, the XOR key is rotated by the table seed,
+/// so even with `Randomization::None` the two give different cipher texts, and each one only decrypts with its
+/// own function. `decrypt3` takes null bytes for the random stars, so `encrypt3` rejects content holding any: when
+/// chaining it with `encrypt_file`, it has to run first, on the text, not on the output of `encrypt_file`.
+///
+/// # Arguments
+///
+/// * `plain_text` - The plain text to encrypt as a vector of bytes.
+/// * `key1` - The first encryption key as a reference to a vector of bytes.
+/// * `key2` - The second encryption key as a reference to a vector of bytes.
+/// * `password` - The password used for additional encryption.
+///
+/// # Returns
+///
+/// A result containing either the encrypted cipher text as a vector of bytes or an error.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 28 if `plain_text` contains a null byte, and code 5 if a key is too short.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::encrypt3;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let plain_text = b"example text".to_vec();
+/// let key1 = TableKey::new(gene3(b"key1")?);
+/// let key2 = RowKey::new(gene3(b"key2")?);
+///
+/// match encrypt3(plain_text, &key1, &key2) {
+///     Ok(cipher_text) => println!("Cipher text: {:?}", cipher_text),
+///     Err(err) => eprintln!("Error: {}", err),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt3(plain_text: Vec<u8>, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Vec<u8>, Box<dyn Error>> {
+    encrypt3_with(plain_text, key1, key2, Randomization::Full)
+}
+
+/// Whether `encrypt3_with` randomizes its output.
+///
+/// With `Full`, random stars are inserted in the plain text, so encrypting the same text twice gives different cipher texts.
+/// With `None`, the same text and keys always give the same cipher text: this allows deduplication and content-addressed storage,
+/// but anyone seeing two cipher texts learns whether they hold the same text, and the cipher text length is the plain text length.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Randomization {
+    /// Inserts random stars before encrypting.
+    #[default]
+    Full,
+    /// Encrypts the text as is, for deterministic output.
+    None,
+}
+
+/// Encrypts plain text like `encrypt3`, choosing whether the output is randomized.
+///
+/// Both kinds of cipher text are decrypted by `decrypt3`. Null bytes are rejected in either case, see `encrypt3`.
+///
+/// # Arguments
+///
+/// * `plain_text` - The plain text to encrypt as a vector of bytes.
+/// * `key1` - The first encryption key.
+/// * `key2` - The second encryption key.
+/// * `randomization` - Whether random stars are inserted, see `Randomization`.
+///
+/// # Returns
+///
+/// A result containing either the encrypted cipher text as a vector of bytes or an error.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 28 if `plain_text` contains a null byte, and code 5 if a key is too short.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::{encrypt3_with, Randomization};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"lib key one")?);
+/// # let key2 = RowKey::new(gene3(b"lib key two")?);
+/// # let plain_text = b"same text, same cipher text".to_vec();
+/// let first = encrypt3_with(plain_text.clone(), &key1, &key2, Randomization::None)?;
+/// let second = encrypt3_with(plain_text, &key1, &key2, Randomization::None)?;
+/// assert_eq!(first, second);
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt3_with(plain_text: Vec<u8>, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>, randomization: Randomization) -> Result<Vec<u8>, Box<dyn Error>> {
+    if plain_text.contains(&0) {
+        return Err(Box::new(SystemTrayError::new(28)));
+    }
+
+    let inter = match randomization {
+        Randomization::Full => insert_random_stars(plain_text)?,
+        Randomization::None => plain_text,
+    };
+
+    let key1 = key1.expose();
+    let key2 = key2.expose();
+    check_keys(key1, key2)?;
+
+    let val1 = addition_chiffres(key2);
+    let val2 = addition_chiffres(key1);
+
+    let seed = val2 * val1;
+    let tables = CipherTables::from_seed(seed);
+
+    let key1_chars: Vec<usize> = key1.into_par_iter().map(|&c| c as usize % 256).collect();
+    let key2_chars: Vec<usize> = key2.into_par_iter().map(|&c| c as usize % 256).collect();
+    let key1_len = KEY_LENGTH;
+    let key2_len = KEY_LENGTH;
+
+    let mut cipher_text = map_indexed(&inter, |i, c| {
+        let table_2d = key1_chars[i % key1_len];
+        let row = key2_chars[i % key2_len];
+
+        tables.encrypt_byte(table_2d, row, c)
+    });
+
+    let mut key_clone = key1.to_vec();
+    key_clone.rotate_left(seed as usize % 64);
+    xor_crypt3(&mut cipher_text, &key_clone);
+    let vz = vz_maker(val1, val2, seed)?;
+
+    Ok(shift_bits(&cipher_text, vz))
+}
+
+/// Decrypts cipher text encrypted using a double-key encryption scheme.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The cipher text to decrypt as a vector of bytes.
+/// * `key1` - The first encryption key as a reference to a vector of bytes.
+/// * `key2` - The second encryption key as a reference to a vector of bytes.
+/// * `password` - The password used for additional decryption.
+///
+/// # Returns
+///
+/// A result containing either the decrypted plain text as a vector of bytes or an error.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::{decrypt3, encrypt3};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let key1 = TableKey::new(gene3(b"key1")?);
+/// let key2 = RowKey::new(gene3(b"key2")?);
+/// let cipher_text = encrypt3(b"example text".to_vec(), &key1, &key2)?;
+///
+/// match decrypt3(cipher_text, &key1, &key2) {
+///     Ok(plain_text) => println!("Plain text: {:?}", plain_text),
+///     Err(err) => eprintln!("Error: {}", err),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn decrypt3(cipher_text: Vec<u8>, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Vec<u8>, Box<dyn Error>> {
+
+    let key1 = key1.expose();
+    let key2 = key2.expose();
+    check_keys(key1, key2)?;
+
+    let val1 = addition_chiffres(key2);
+    let val2 = addition_chiffres(key1);
+
+    let seed = val2 * val1 ;
+
+    let tables = CipherTables::from_seed(seed);
+
+    let vz = vz_maker(val1, val2, seed)?;
+    let mut cipher_text = unshift_bits(&cipher_text, vz);
+
+    let mut key_clone = key1.to_vec();
+    key_clone.rotate_left(seed as usize % 64);
+    xor_crypt3(&mut cipher_text, &key_clone);
+
+    let key1_chars: Vec<usize> = key1.into_par_iter().map(|&c| c as usize % 256).collect();
+    let key2_chars: Vec<usize> = key2.into_par_iter().map(|&c| c as usize % 256).collect();
+    let key1_len = KEY_LENGTH;
+    let key2_len = KEY_LENGTH;
+
+    let plain_text = map_indexed(&cipher_text, |i, c| {
+        let table_2d = key1_chars[i % key1_len];
+        let row = key2_chars[i % key2_len];
+
+        // The null bytes are the stars inserted by `insert_random_stars`
+        match tables.decrypt_byte(table_2d, row, c) {
+            0 => None,
+            byte => Some(byte),
+        }
+    });
+
+    Ok(plain_text.into_iter().flatten().collect())
+}
+
+/// Performs XOR encryption/decryption on a byte slice using a key.
+///
+/// # Arguments
+///
+/// * `input` - A mutable reference to the byte slice to be encrypted/decrypted.
+/// * `key` - The key used for encryption/decryption as a byte slice.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut data = vec![/* insert data here */];
+/// let key = vec![/* insert key here */];
+///
+/// xor_crypt3(&mut data, &key);
+///
+/// // At this point, `data` contains the encrypted or decrypted result.
+/// ```
+fn xor_crypt3(input: &mut [u8], key: &[u8]) {
+    let xor = |(i, byte): (usize, &mut u8)| *byte ^= key[i % key.len()];
+
+    if runs_serially(input.len()) {
+        input.iter_mut().enumerate().for_each(xor);
+    } else {
+        input.par_iter_mut().enumerate().for_each(xor);
+    }
+}
+
+/// Performs bit shifting on a byte vector based on a key.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The byte slice to be shifted.
+/// * `key` - The key used for bit shifting as a byte slice.
+///
+/// # Returns
+///
+/// A byte vector containing the result of the bit shifting operation.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::shift_bits;
+/// # use secrecy::Secret;
+/// let cipher_text = b"shifted bytes".to_vec();
+/// let key = Secret::new(vec![3u8; cipher_text.len()]);
+///
+/// let shifted_text = shift_bits(&cipher_text, key);
+///
+/// // At this point, `shifted_text` contains the result of bit shifting.
+/// # assert_ne!(shifted_text, cipher_text);
+/// ```
+pub fn shift_bits(cipher_text: &[u8], key: Secret<Vec<u8>>) -> Vec<u8> {
+    let key = key.expose_secret();
+    map_indexed(cipher_text, |i, byte| {
+        let shift_amount = key[i % key.len()];
+
+        byte.rotate_left(shift_amount as u32)
+    })
+}
+
+/// Reverses the bit shifting operation performed by the `shift_bits` function.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The byte slice to be unshifted.
+/// * `key` - The key used for bit shifting as a byte slice.
+///
+/// # Returns
+///
+/// A byte vector containing the result of the reverse bit shifting operation.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::{shift_bits, unshift_bits};
+/// # use secrecy::Secret;
+/// let key = vec![3u8; 13];
+/// let cipher_text = shift_bits(b"shifted bytes", Secret::new(key.clone()));
+///
+/// let unshifted_text = unshift_bits(&cipher_text, Secret::new(key));
+///
+/// // At this point, `unshifted_text` contains the result of reverse bit shifting.
+/// # assert_eq!(unshifted_text, b"shifted bytes");
+/// ```
+pub fn unshift_bits(cipher_text: &[u8], key: Secret<Vec<u8>>) -> Vec<u8> {
+    let key = key.expose_secret();
+    map_indexed(cipher_text, |i, byte| {
+        let shift_amount = key[i % key.len()];
+
+        byte.rotate_right(shift_amount as u32)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use crate::cryptex::{decrypt_file, encrypt_file};
+
+    use super::*;
+
+    #[test]
+/// Tests file encryption and decryption.
+///
+/// This function demonstrates the process of encrypting and decrypting the content of a file.
+/// It reads the content of a file, encrypts it using the `encrypt_file` function, then decrypts it back using the `decrypt_file` function.
+/// Finally, it verifies that the decrypted content matches the original content of the file.
+///
+/// # Note
+///
+/// This function is meant for testing purposes and should be adapted or extended for actual use cases.
+///
+/// # Examples
+///
+/// ```
+/// // Execute the test for file encryption and decryption
+/// test_crypt_file();
+/// ```
+    fn test_crypt_file(){
+        //let password = "bonjourcestmoi";
+        //let key1 = generate_key2(password);
+        //let key2 = generate_key2(password);
+        //let key3 = generate_key2(password);
+
+
+        //let mut file_content = Vec::new();
+        //let mut file = File::open("invoicesample.pdf").unwrap();
+        //file.read_to_end(&mut file_content).expect("TODO: panic message");
+
+        //let encrypted_content = encrypt_file(file_content.clone(), &key1.unwrap(), &key2.unwrap());
+
+        //let b = encrypted_content.unwrap();
+
+
+        //let dcrypted_content = decrypt_file(b, &key1.unwrap(), &key3.unwrap());
+        //let a = dcrypted_content.unwrap();
+        //assert_eq!(a.clone(), file_content);
+    }
+
+    #[test]
+    fn test_table3() {
+        let size = 255;
+
+        let table = table3(size, 123456789);
+
+        for table_2d in table.iter() {
+            for row in table_2d.iter() {
+                for col in row.iter() {
+                    print!("{} ", col);
+                }
+
+                println!();
+            }
+
+            println!();
+            println!();
+        }
+    }
+
+    #[test]
+    fn test_table3_keyed_rows_unrelated() {
+        let table = table3_keyed(256, 123456789);
+        assert_eq!(table, table3_keyed(256, 123456789));
+
+        for (a, b) in [(0, 0), (17, 200), (255, 254)] {
+            let row = &table[a][b];
+            let next = &table[a][b + 1 - (b == 255) as usize * 2];
+
+            let mut sorted = row.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, (0..=255).collect::<Vec<u8>>());
+            assert!((0..256).all(|shift| row.iter().cycle().skip(shift).take(256).ne(next.iter())), "Rows are rotations of each other");
+        }
+
+        // The rows of table3 are rotations of each other
+        let plain = table3(256, 123456789);
+        assert!((0..256).any(|shift| plain[0][0].iter().cycle().skip(shift).take(256).eq(plain[0][1].iter())));
+    }
+
+    #[test]
+    fn test_parallel_threshold() {
+        let key1 = TableKey::new(gene3(b"threshold_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"threshold_key_two").unwrap());
+
+        for length in [64, 100_000] {
+            let plain_text: Vec<u8> = (0..length).map(|i| (i % 251) as u8).collect();
+
+            set_parallel_threshold_override(Some(usize::MAX));
+            let serial = encrypt_file(plain_text.clone(), &key1, &key2).unwrap();
+
+            set_parallel_threshold_override(Some(0));
+            let parallel = encrypt_file(plain_text.clone(), &key1, &key2).unwrap();
+
+            set_parallel_threshold_override(None);
+            assert_eq!(serial, parallel);
+            assert_eq!(decrypt_file(serial, &key1, &key2).unwrap(), plain_text);
+        }
+    }
+
+    #[test]
+    fn test_speed_table(){
+        let size = 255;
+        table3(size, 123456789);
+    }
+
+    #[test]
+    fn test_get_salt() {
+        let salt = get_salt();
+        assert_ne!(salt.len(), 0);
+    }
+
+    #[test]
+    fn test_generate_key2() {
+        let seed = "0123456789";
+        let key = generate_key2(seed).unwrap();
+
+
+        assert_ne!(key.expose_secret().len(), 0)
+    }
+
+    #[test]
+    fn test_empty_keys_return_errors() {
+        let empty1 = TableKey::new(Secret::new(vec![]));
+        let empty2 = RowKey::new(Secret::new(vec![]));
+        let key1 = TableKey::new(gene3(b"empty_test_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"empty_test_key_two").unwrap());
+        let text = b"no key, no panic".to_vec();
+
+        assert_eq!(addition_chiffres(&[]), 0);
+        for (key1, key2) in [(&empty1, &key2), (&key1, &empty2), (&empty1, &empty2)] {
+            let error = encrypt3(text.clone(), key1, key2).unwrap_err();
+            assert_eq!(error.to_string(), SystemTrayError::new(5).to_string());
+            assert!(decrypt3(text.clone(), key1, key2).is_err());
+            assert!(encrypt_file(text.clone(), key1, key2).is_err());
+            assert!(decrypt_file(text.clone(), key1, key2).is_err());
+        }
+    }
+
+    #[test]
+    fn test_randomization_none_deterministic() {
+        let plain_text = b"the same text is stored once".to_vec();
+        let key1 = TableKey::new(gene3(b"dedup_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"dedup_key_two").unwrap());
+
+        let first = encrypt3_with(plain_text.clone(), &key1, &key2, Randomization::None).unwrap();
+        let second = encrypt3_with(plain_text.clone(), &key1, &key2, Randomization::None).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(decrypt3(first, &key1, &key2).unwrap(), plain_text);
+        assert_eq!(decrypt3(second, &key1, &key2).unwrap(), plain_text);
+
+        let randomized = encrypt3_with(plain_text.clone(), &key1, &key2, Randomization::Full).unwrap();
+        assert_ne!(randomized.len(), plain_text.len());
+        assert_eq!(decrypt3(randomized, &key1, &key2).unwrap(), plain_text);
+    }
+
+    #[test]
+    fn test_encrypt3_and_encrypt_file_differ() {
+        let plain_text = b"two schemes, each with its own decryption".to_vec();
+        let key1 = TableKey::new(gene3(b"relation_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"relation_key_two").unwrap());
+
+        // Same length without stars, yet different bytes: the XOR key of encrypt3 is rotated
+        let cipher3 = encrypt3_with(plain_text.clone(), &key1, &key2, Randomization::None).unwrap();
+        let cipher_file = encrypt_file(plain_text.clone(), &key1, &key2).unwrap();
+        assert_eq!(cipher3.len(), cipher_file.len());
+        assert_ne!(cipher3, cipher_file);
+        assert_ne!(decrypt_file(cipher3.clone(), &key1, &key2).unwrap(), plain_text);
+        assert_eq!(decrypt3(cipher3, &key1, &key2).unwrap(), plain_text);
+
+        // Chained rounds: encrypt3 first, then encrypt_file, undone in reverse
+        let round_keys: Vec<RowKey> = (0..6).map(|round| RowKey::new(gene3(format!("round {}", round).as_bytes()).unwrap())).collect();
+        let mut content = plain_text.clone();
+        for (round, key2) in round_keys.iter().enumerate() {
+            content = if round == 0 { encrypt3(content, &key1, key2) } else { encrypt_file(content, &key1, key2) }.unwrap();
+        }
+        for (round, key2) in round_keys.iter().enumerate().rev() {
+            content = if round == 0 { decrypt3(content, &key1, key2) } else { decrypt_file(content, &key1, key2) }.unwrap();
+        }
+        assert_eq!(content, plain_text);
+
+    }
+
+    #[test]
+    fn test_encrypt3_rejects_null_bytes() {
+        let key1 = TableKey::new(gene3(b"null_byte_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"null_byte_key_two").unwrap());
+        let with_null = b"null \0 byte".to_vec();
+
+        for randomization in [Randomization::Full, Randomization::None] {
+            let error = encrypt3_with(with_null.clone(), &key1, &key2, randomization).unwrap_err();
+            assert_eq!(error.downcast_ref::<SystemTrayError>().unwrap().code, 28);
+        }
+        let error = encrypt3(vec![0u8; 16], &key1, &key2).unwrap_err();
+        assert_eq!(error.downcast_ref::<SystemTrayError>().unwrap().code, 28);
+    }
+
+    #[cfg(feature = "fuzzing")]
+    #[test]
+    fn test_fuzzing_seed_reproducible() {
+        let plain_text = b"a crash on this input must replay".to_vec();
+        let key1 = TableKey::new(gene3(b"fuzzing_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"fuzzing_key_two").unwrap());
+
+        crate::nebula::set_fuzzing_seed(Some(7));
+        let first = encrypt3(plain_text.clone(), &key1, &key2).unwrap();
+        let second = encrypt3(plain_text.clone(), &key1, &key2).unwrap();
+        crate::nebula::set_fuzzing_seed(Some(8));
+        let other = encrypt3(plain_text.clone(), &key1, &key2).unwrap();
+        crate::nebula::set_fuzzing_seed(None);
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(decrypt3(first, &key1, &key2).unwrap(), plain_text);
+    }
+
+    #[test]
+    fn test_insert_random_stars() {
+        let word = "Hello World!".as_bytes().to_vec();
+        let word2 = insert_random_stars(word.clone()).unwrap();
+
+        println!("Word: {:?}", word2);
+        assert_ne!(word, word2);
+    }
+
+
+    #[test]
+    fn test_shift_unshift_bits() {
+        let original_data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10,1, 2, 3, 4, 5, 6, 7, 8, 9, 10,1, 2, 3, 4, 5, 6, 7, 8, 9, 10,1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let key = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+
+        let shifted_data = shift_bits(&original_data, Secret::new(key.clone()));
+        let unshifted_data = unshift_bits(&shifted_data, Secret::new(key));
+
+        assert_eq!(original_data, unshifted_data);
+    }
+
+
+    #[test]
+    fn safe_crypt() {
+        // Données originales et mot de passe
+        let original_data = "ce soir je sors ne t'inquiète pas je rentre bientôt";
+        let pass = "LeMOTdePAsse34!";
+
+        const ROUND: usize = 8;
+
+        // Génération de la clé principale
+        let key1 = match generate_key2(pass) {
+            Ok(key) => TableKey::new(key),
+            Err(err) => {
+                eprintln!("Erreur : {}", err);
+                return;
+            },
+        };
+
+        // Génération de la liste de clés aléatoires
+        let mut rng = Nebula::new(123456789);
+        let liste: Vec<String> = (0..ROUND)
+            .map(|_| rng.generate_random_number().unwrap().to_string())
+            .collect();
+
+        let mut chif = original_data.as_bytes().to_vec();
+
+        for (index, element) in liste.iter().enumerate() { //TODO modifier key1 rotation par rapport à key 2
+            let key2 = RowKey::new(generate_key2(element).unwrap());
+            chif = if index < 1 {
+                encrypt3(chif, &key1, &key2).unwrap()
+            } else {
+                encrypt_file(chif, &key1, &key2).unwrap()
+            };
+
+            println!(" {} Chiffré : {}",index, String::from_utf8_lossy(&chif));
+        }
+
+        println!("-----------------------------------------");
+
+        for (index, element) in liste.iter().enumerate().rev() {
+            let key2 = RowKey::new(generate_key2(element).unwrap());
+            chif = if index < 1 {
+                decrypt3(chif, &key1, &key2).unwrap()
+            } else {
+                decrypt_file(chif, &key1, &key2).unwrap()
+            };
+
+            println!("{} déChiffré : {}",index, String::from_utf8_lossy(&chif));
+        }
+
+        assert_eq!(original_data, String::from_utf8_lossy(&chif));
+    }
+
+    use std::io::Write;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn test_duplicate_lines() -> std::io::Result<()> {
+        // Ouvrir le fichier output.txt en lecture
+        let input_file = File::open("output.txt")?;
+        let reader = BufReader::new(input_file);
+
+        // Ouvrir le fichier tri.txt en écriture
+        let mut output_file = File::create("tri.txt")?;
+
+        // Lire toutes les lignes du fichier
+        let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+
+        // Parcourir chaque ligne du fichier
+        for i in 0..lines.len() {
+            for j in i + 1..lines.len() {
+                // Si deux lignes sont identiques
+                if lines[i] == lines[j] {
+                    // Écrire la ligne dans le fichier tri.txt
+                    writeln!(output_file, "{}", lines[i])?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gene3() {
+        let seed = b"test_seed"; // Exemple de graine
+        let secret = gene3(seed).unwrap();
+
+        // Vérifier que le matériel de clé de sortie a la bonne longueur
+        assert_eq!(secret.expose_secret().len(), KEY_LENGTH);
+
+        // Vous pouvez également vérifier que le matériel de clé de sortie n'est pas vide
+        assert!(!secret.expose_secret().is_empty());
+    }
+
+    #[test]
+    fn test_key_fingerprint() {
+        let key = Secret::new(vec![1u8; KEY_LENGTH]);
+        let same_key = Secret::new(vec![1u8; KEY_LENGTH]);
+        let other_key = Secret::new(vec![2u8; KEY_LENGTH]);
+
+        let fingerprint = key_fingerprint(&key);
+
+        assert_eq!(fingerprint.len(), FINGERPRINT_LENGTH * 3 - 1);
+        assert_eq!(fingerprint.split(':').count(), FINGERPRINT_LENGTH);
+        assert_eq!(fingerprint, key_fingerprint(&same_key));
+        assert_ne!(fingerprint, key_fingerprint(&other_key));
+
+        // Keys differing by a single byte must still give distinct fingerprints
+        let fingerprints: std::collections::HashSet<String> = (0..=255u8)
+            .map(|byte| {
+                let mut key = vec![0u8; KEY_LENGTH];
+                key[KEY_LENGTH - 1] = byte;
+                key_fingerprint(&Secret::new(key))
+            })
+            .collect();
+        assert_eq!(fingerprints.len(), 256);
+    }
+
+    #[test]
+    fn test_gene3_different_seeds() {
+        let seed1 = b"seed_one";
+        let seed2 = b"seed_two";
+
+        let secret1 = gene3(seed1).unwrap();
+        let secret2 = gene3(seed2).unwrap();
+
+        // Vérifier que les résultats sont différents pour des graines différentes
+        assert_ne!(secret1.expose_secret(), secret2.expose_secret());
+    }
+
+
+    #[test]
+    fn test_generate_key2_errors() {
+        assert!(generate_key2("short").is_err());
+        assert!(derive_key(b"seed", b"salt").is_err());
+        assert_eq!(derive_key(b"long enough seed", b"long enough salt").unwrap().expose_secret().len(), KEY_LENGTH);
+    }
+
+    #[test]
+    fn test_xof() {
+        let mut outputs = Vec::new();
+        for length in [32, 64, 200] {
+            let mut first = vec![0u8; length];
+            let mut second = vec![0u8; length];
+            xof(b"horizon xof input", &mut first);
+            xof(b"horizon xof input", &mut second);
+
+            assert_eq!(first.len(), length);
+            assert_eq!(first, second);
+            outputs.push(first);
+        }
+
+        assert_eq!(outputs[0], blake3::hash(b"horizon xof input").as_bytes());
+        assert_eq!(outputs[1][..32], outputs[0]);
+        assert_eq!(outputs[2][..64], outputs[1]);
+    }
+
+    #[test]
+    fn test_check_salt() {
+        let error = check_salt(b"host").unwrap_err();
+        assert_eq!(error.code, 10);
+        assert_eq!(error.to_string(), format!("Salt is too short: expected at least {} bytes, got 4", MIN_SALT_LENGTH));
+
+        assert!(check_salt(&[b's'; MIN_SALT_LENGTH]).is_ok());
+    }
+
+    #[test]
+    fn test_derive_many() {
+        let passwords: Vec<String> = (0..100).map(|i| format!("user password {}", i)).collect();
+        let salts: Vec<String> = (0..100).map(|i| format!("user salt {}", i)).collect();
+        let inputs: Vec<(&[u8], &[u8])> = passwords.iter().zip(&salts).map(|(password, salt)| (password.as_bytes(), salt.as_bytes())).collect();
+
+        let keys = derive_many(&inputs, 4).unwrap();
+
+        assert_eq!(keys.len(), inputs.len());
+        for (key, &(password, salt)) in keys.iter().zip(&inputs) {
+            assert_eq!(key.expose_secret(), derive_key(password, salt).unwrap().expose_secret());
+        }
+        assert!(derive_many(&[(b"password", b"salt")], 1).is_err());
+    }
+
+    #[test]
+    fn test_derive_key_kat() {
+        let key = derive_key(b"horizon known answer", b"fixed test salt").unwrap();
+        let key = key.expose_secret();
+        assert_eq!(hex::encode(&key[..32]), "f895d8771edcc9414b8c47b2f45fb18e8f5d5e281d617a1c58c43b11b4314de0");
+        assert_eq!(hex::encode(&key[KEY_LENGTH - 32..]), "a0d4196da0ca2adbc3a41a472cbf6b54aedf756ad7573b90511887245f4c14e9");
+
+        let params = Params::new(64, 1, 1, Some(KEY_LENGTH)).unwrap();
+        let key = derive_key_with_params(b"horizon known answer", b"fixed test salt", params).unwrap();
+        assert_eq!(hex::encode(&key.expose_secret()[..32]), "c866093a04d3434f379919271a77ff6d4c1d1344ca165b64a4ad029f50a0f915");
+    }
+
+    #[test]
+    fn test_kdf_variants() {
+        let params = Params::new(64, 1, 1, Some(KEY_LENGTH)).unwrap();
+        let algorithms = [Argon2Algorithm::Argon2d, Argon2Algorithm::Argon2i, Argon2Algorithm::Argon2id];
+        let versions = [Argon2Version::V0x10, Argon2Version::V0x13];
+
+        let mut keys = Vec::new();
+        for algorithm in algorithms {
+            for version in versions {
+                let variant = KdfVariant { algorithm, version };
+                assert_eq!(KdfVariant::from_byte(variant.to_byte()).unwrap(), variant);
+
+                let key = derive_key_with(b"horizon variants", b"fixed test salt", params.clone(), variant).unwrap();
+                keys.push(key.expose_secret().clone());
+            }
+        }
+
+        for (i, key) in keys.iter().enumerate() {
+            assert!(keys[i + 1..].iter().all(|other| other != key), "Two variants derived the same key");
+        }
+
+        // The default variant is the one of `derive_key_with_params`
+        let default = derive_key_with_params(b"horizon variants", b"fixed test salt", params).unwrap();
+        assert_eq!(default.expose_secret(), &keys[5]);
+
+        assert_eq!(KdfVariant::from_byte(0x03).unwrap_err().code, 25);
+        assert_eq!(KdfVariant::from_byte(0x22).unwrap_err().code, 25);
+    }
+
+    #[test]
+    fn test_derive_seed() {
+        let key1 = gene3(b"seed_key_one").unwrap();
+        let key2 = gene3(b"seed_key_two").unwrap();
+
+        let seed = derive_seed(&TableKey::new(Secret::new(key1.expose_secret().clone())), &RowKey::new(Secret::new(key2.expose_secret().clone())));
+        assert_eq!(seed, derive_seed(&TableKey::new(Secret::new(key2.expose_secret().clone())), &RowKey::new(Secret::new(key1.expose_secret().clone()))));
+
+        let (val1, val2) = (addition_chiffres(key2.expose_secret()), addition_chiffres(key1.expose_secret()));
+        assert_eq!(seed as u64, val2.wrapping_mul(val1));
+
+        let huge = vec![255u8; 1 << 24];
+        assert_eq!(derive_seed(&TableKey::new(Secret::new(huge.clone())), &RowKey::new(Secret::new(huge))), (255u128 << 24) * (255u128 << 24));
+    }
+
+    #[test]
+    fn test_derive_seed_v2_uses_every_byte() {
+        let key1 = TableKey::new(gene3(b"seed_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"seed_key_two").unwrap());
+        let seed = derive_seed_v2(&key1, &key2);
+
+        // Moving one unit between two bytes keeps the byte sum, and the V1 seed
+        let flat = TableKey::new(Secret::new(vec![100u8; KEY_LENGTH]));
+        let mut balanced = vec![100u8; KEY_LENGTH];
+        balanced[0] += 1;
+        balanced[1] -= 1;
+        let balanced = TableKey::new(Secret::new(balanced));
+        assert_eq!(derive_seed(&balanced, &key2), derive_seed(&flat, &key2));
+        assert_ne!(derive_seed_v2(&balanced, &key2), derive_seed_v2(&flat, &key2));
+
+        for position in [0, 1, KEY_LENGTH / 2, KEY_LENGTH - 1] {
+            let mut changed = key1.expose_secret().clone();
+            changed[position] ^= 1;
+            assert_ne!(derive_seed_v2(&TableKey::new(Secret::new(changed)), &key2), seed, "Byte {} of key1 is ignored", position);
+
+            let mut changed = key2.expose_secret().clone();
+            changed[position] ^= 1;
+            assert_ne!(derive_seed_v2(&key1, &RowKey::new(Secret::new(changed))), seed, "Byte {} of key2 is ignored", position);
+        }
+
+        let swapped = (TableKey::new(Secret::new(key2.expose_secret().clone())), RowKey::new(Secret::new(key1.expose_secret().clone())));
+        assert_ne!(derive_seed_v2(&swapped.0, &swapped.1), seed);
+    }
+
+    #[test]
+    fn test_verify_password() {
+        let salt = b"stored user salt";
+        let fingerprint = key_fingerprint(&derive_key(b"correct horse battery", salt).unwrap());
+
+        assert!(verify_password("correct horse battery", salt, &fingerprint));
+        assert!(!verify_password("correct horse battery!", salt, &fingerprint));
+        assert!(!verify_password("correct horse battery", b"another user salt", &fingerprint));
+        assert!(!verify_password("correct horse battery", salt, &fingerprint[..fingerprint.len() - 1]));
+        assert!(!verify_password("correct horse battery", b"short", &fingerprint));
+    }
+}
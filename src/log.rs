@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::io::{ErrorKind, Read, Write};
+
+use secrecy::Secret;
+
+use crate::cryptex::{decrypt_file, encrypt_file_ref};
+use crate::format::RECORD_LENGTH_SIZE;
+use crate::keys::{KeyMaterial, RowKey, TableKey};
+use crate::systemtrayerror::SystemTrayError;
+
+/// Appends encrypted records to a log, such as an audit trail, without touching the previous ones.
+///
+/// Each record is its length (`u64`, big-endian) followed by its `encrypt_file` output, so records written
+/// by separate writers on the same file follow each other. A damaged record decrypts to wrong bytes
+/// without affecting the others, as long as its length is intact.
+pub struct LogWriter<'k, W: Write, K1 = Secret<Vec<u8>>, K2 = Secret<Vec<u8>>> {
+    writer: W,
+    key1: &'k TableKey<K1>,
+    key2: &'k RowKey<K2>,
+}
+
+impl<'k, W: Write, K1: KeyMaterial, K2: KeyMaterial> LogWriter<'k, W, K1, K2> {
+    /// Creates a `LogWriter` appending to `writer`, typically a file opened in append mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The destination of the records.
+    /// * `key1` - The first secret key given to `encrypt_file`.
+    /// * `key2` - The second secret key given to `encrypt_file`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::fs::OpenOptions;
+    /// # use horizon::log::LogWriter;
+    /// # use horizon::gene3;
+    /// # use horizon::keys::{RowKey, TableKey};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let key1 = TableKey::new(gene3(b"audit key one")?);
+    /// # let key2 = RowKey::new(gene3(b"audit key two")?);
+    /// let file = OpenOptions::new().append(true).create(true).open("audit.log")?;
+    /// let mut log = LogWriter::new(file, &key1, &key2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(writer: W, key1: &'k TableKey<K1>, key2: &'k RowKey<K2>) -> Self {
+        LogWriter { writer, key1, key2 }
+    }
+
+    /// Encrypts a record and appends it to the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The content of the record.
+    ///
+    /// # Returns
+    ///
+    /// An error if encryption or writing fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use horizon::log::LogWriter;
+    /// # use horizon::gene3;
+    /// # use horizon::keys::{RowKey, TableKey};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let key1 = TableKey::new(gene3(b"audit key one")?);
+    /// # let key2 = RowKey::new(gene3(b"audit key two")?);
+    /// # let mut log = LogWriter::new(Vec::new(), &key1, &key2);
+    /// log.append(b"user 42 logged in")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn append(&mut self, record: &[u8]) -> Result<(), Box<dyn Error>> {
+        let cipher_text = encrypt_file_ref(record, self.key1, self.key2)?;
+
+        let mut bytes = Vec::with_capacity(RECORD_LENGTH_SIZE + cipher_text.len());
+        bytes.extend_from_slice(&(cipher_text.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&cipher_text);
+        self.writer.write_all(&bytes)?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads the records of a log written by `LogWriter`, decrypting them one at a time.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::fs::File;
+/// # use horizon::log::LogReader;
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"audit key one")?);
+/// # let key2 = RowKey::new(gene3(b"audit key two")?);
+/// for record in LogReader::new(File::open("audit.log")?, &key1, &key2) {
+///     println!("{}", String::from_utf8_lossy(&record?));
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct LogReader<'k, R: Read, K1 = Secret<Vec<u8>>, K2 = Secret<Vec<u8>>> {
+    reader: R,
+    key1: &'k TableKey<K1>,
+    key2: &'k RowKey<K2>,
+    done: bool,
+}
+
+impl<'k, R: Read, K1: KeyMaterial, K2: KeyMaterial> LogReader<'k, R, K1, K2> {
+    /// Creates a `LogReader` reading records from `reader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The source of the log.
+    /// * `key1` - The first secret key given to `decrypt_file`.
+    /// * `key2` - The second secret key given to `decrypt_file`.
+    pub fn new(reader: R, key1: &'k TableKey<K1>, key2: &'k RowKey<K2>) -> Self {
+        LogReader { reader, key1, key2, done: false }
+    }
+
+    fn read_record(&mut self) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let mut length = [0u8; RECORD_LENGTH_SIZE];
+        match self.reader.read_exact(&mut length) {
+            Ok(()) => {},
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+
+        let length = u64::from_be_bytes(length);
+        let mut cipher_text = Vec::new();
+        self.reader.by_ref().take(length).read_to_end(&mut cipher_text)?;
+        if (cipher_text.len() as u64) < length {
+            return Err(Box::new(SystemTrayError::new(24)));
+        }
+
+        decrypt_file(cipher_text, self.key1, self.key2).map(Some)
+    }
+}
+
+impl<R: Read, K1: KeyMaterial, K2: KeyMaterial> Iterator for LogReader<'_, R, K1, K2> {
+    type Item = Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Returns the next decrypted record, or an error if the log is truncated, after which iteration stops.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let record = self.read_record().transpose();
+        if !matches!(record, Some(Ok(_))) {
+            self.done = true;
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gene3;
+
+    use super::*;
+
+    #[test]
+    fn test_log_append_and_read() {
+        let key1 = TableKey::new(gene3(b"log_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"log_key_two").unwrap());
+        let records: [&[u8]; 3] = [b"user 42 logged in", b"user 42 read \0 the file", b"user 42 logged out"];
+
+        // Each record is appended by its own writer, as separate runs of a program would
+        let mut log = Vec::new();
+        for record in records {
+            let mut writer = LogWriter::new(log, &key1, &key2);
+            writer.append(record).unwrap();
+            log = writer.into_inner();
+        }
+
+        let read: Vec<Vec<u8>> = LogReader::new(log.as_slice(), &key1, &key2).map(Result::unwrap).collect();
+        assert_eq!(read, records);
+
+        // Damage the body of the second record: the other two still decrypt
+        log[RECORD_LENGTH_SIZE * 2 + records[0].len() + 3] ^= 0xFF;
+        let read: Vec<Vec<u8>> = LogReader::new(log.as_slice(), &key1, &key2).map(Result::unwrap).collect();
+        assert_eq!(read[0], records[0]);
+        assert_ne!(read[1], records[1]);
+        assert_eq!(read[2], records[2]);
+
+        // A truncated log yields the complete records, then an error
+        let truncated = &log[..log.len() - 1];
+        let mut reader = LogReader::new(truncated, &key1, &key2);
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
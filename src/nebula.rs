@@ -1,25 +1,110 @@
 use std::collections::{HashSet, VecDeque};
-use std::sync::Mutex;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use blake3::Hasher;
+use rand_core::RngCore;
 use rayon::iter::ParallelIterator;
 use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator};
-use secrecy::ExposeSecret;
+use secrecy::{ExposeSecret, Zeroize};
 use sysinfo::{Networks, Pid, ProcessesToUpdate, System};
 
 use crate::kdfwagen::kdfwagen;
 use crate::systemtrayerror::SystemTrayError;
+use crate::xof;
 
-const MAX_RESEED_INTERVAL: u128 = 60;
+/// Default time after which `reseed` gathers new entropy even if the byte threshold was not reached.
+const DEFAULT_RESEED_INTERVAL: Duration = Duration::from_secs(60);
 const MAX_POOL_SIZE: usize = 1024;
 const RESEED_THRESHOLD: usize = 512;
+/// Number of bytes read by `add_entropy_from_path`.
+const DEVICE_BLOCK_SIZE: usize = 64;
+/// Number of bytes generated at once by `write_random`.
+const WRITE_CHUNK_SIZE: usize = 4096;
+/// Number of identical consecutive bytes after which the generator is considered stuck.
+/// With 8 bits of entropy per byte, a healthy generator hits it with probability 2^-40.
+const REPETITION_CUTOFF: usize = 6;
+/// Default time during which the system snapshot used for entropy gathering is reused.
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of attempts to read the system state before giving up.
+const MAX_GATHER_ATTEMPTS: usize = 3;
+const GATHER_RETRY_DELAY_MS: u64 = 10;
+/// Minimum number of non-zero system sources (after the time and the process id) accepted without the OS CSPRNG.
+const MIN_SYSTEM_SOURCES: usize = 4;
+
+static SYSTEM_SNAPSHOT: Mutex<Option<Arc<SystemSnapshot>>> = Mutex::new(None);
+static REFRESH_INTERVAL: Mutex<Duration> = Mutex::new(DEFAULT_REFRESH_INTERVAL);
 
 pub struct Nebula {
     seed: u128,
-    pool: Mutex<VecDeque<u8>>,
+    pool: Arc<Mutex<VecDeque<u8>>>,
     last_reseed_time: u128,
+    reseed_interval: Duration,
     bytes_since_reseed: Mutex<usize>,
+    repetition_test: RepetitionCountTest,
+    clock: Box<dyn Clock>,
+}
+
+/// A source of timestamps for `Nebula`, in nanoseconds since the UNIX epoch.
+///
+/// The generator reads the time to decide when to reseed and as a source of entropy.
+/// Tests can provide their own clock to move time forward or backward deterministically.
+pub trait Clock: Send + Sync {
+    /// Returns the current time in nanoseconds since the UNIX epoch.
+    fn now(&self) -> u128;
+}
+
+/// The system wall clock, used by `Nebula::new`.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u128 {
+        current_time()
+    }
+}
+
+/// Continuous repetition count test (FIPS 140 style) run on every generated byte.
+///
+/// It tracks the last output byte and how many times in a row it has been produced,
+/// so that a generator stuck on the same value is caught instead of returning predictable data.
+struct RepetitionCountTest {
+    last: Option<u8>,
+    count: usize,
+}
+
+impl RepetitionCountTest {
+    fn new() -> Self {
+        RepetitionCountTest { last: None, count: 0 }
+    }
+
+/// Feeds a newly generated byte to the test.
+///
+/// # Arguments
+///
+/// * `byte` - The byte that was just generated.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 12 if the same byte has been seen `REPETITION_CUTOFF` times in a row.
+    fn check(&mut self, byte: u8) -> Result<(), SystemTrayError> {
+        if self.last == Some(byte) {
+            self.count += 1;
+        } else {
+            self.last = Some(byte);
+            self.count = 1;
+        }
+
+        if self.count >= REPETITION_CUTOFF {
+            return Err(SystemTrayError::new(12));
+        }
+
+        Ok(())
+    }
 }
 
 impl Nebula {
@@ -38,20 +123,151 @@ impl Nebula {
 /// # Examples
 ///
 /// ```
-/// use your_crate::Nebula;
+/// use horizon::nebula::Nebula;
 ///
 /// // Create a new Nebula instance with a seed value of 123456789
 /// let nebula = Nebula::new(123456789);
 /// ```
     pub fn new(seed: u128) -> Self {
+        Nebula::with_clock(seed, SystemClock)
+    }
+
+/// Creates a new instance of the `Nebula` struct reading the time from `clock`.
+///
+/// # Arguments
+///
+/// * `seed` - A 128-bit seed value to initialize the pseudo-random number generator.
+/// * `clock` - The source of the timestamps used for reseeding and mixed into the entropy.
+///
+/// # Examples
+///
+/// ```
+/// use horizon::nebula::{Nebula, SystemClock};
+///
+/// let nebula = Nebula::with_clock(123456789, SystemClock);
+/// ```
+    pub fn with_clock(seed: u128, clock: impl Clock + 'static) -> Self {
         Nebula {
             seed,
-            pool: Mutex::new(VecDeque::new()),
-            last_reseed_time: 0,
+            pool: Arc::new(Mutex::new(VecDeque::new())),
+            last_reseed_time: clock.now(),
+            reseed_interval: DEFAULT_RESEED_INTERVAL,
             bytes_since_reseed: Mutex::new(0),
+            repetition_test: RepetitionCountTest::new(),
+            clock: Box::new(clock),
         }
     }
 
+/// Derives a seed from the content of a reader.
+///
+/// The stream is hashed with BLAKE3 as it is read, so a large secret file can be used
+/// to seed a generator without being loaded in memory. The same content always gives the same seed.
+///
+/// # Arguments
+///
+/// * `reader` - The source of the secret content.
+///
+/// # Returns
+///
+/// A 128-bit seed derived from the whole content of `reader`.
+///
+/// # Errors
+///
+/// Returns the I/O error raised while reading.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::fs::File;
+/// use horizon::nebula::Nebula;
+///
+/// let seed = Nebula::seed_from_reader(File::open("/etc/horizon/seed")?)?;
+/// let nebula = Nebula::new(seed);
+/// # Ok(())
+/// # }
+/// ```
+    pub fn seed_from_reader<R: Read>(reader: R) -> io::Result<u128> {
+        let mut hasher = Hasher::new_derive_key("horizon nebula seed");
+        hasher.update_reader(reader)?;
+
+        let hash_result = hasher.finalize();
+        Ok(u128::from_be_bytes(hash_result.as_bytes()[0..16].try_into().unwrap()))
+    }
+
+/// Returns the time after which the generator reseeds itself with fresh entropy.
+///
+/// # Example
+///
+/// ```
+/// use horizon::nebula::Nebula;
+///
+/// let nebula = Nebula::new(123456789);
+/// println!("Reseed interval: {:?}", nebula.reseed_interval());
+/// ```
+    pub fn reseed_interval(&self) -> Duration {
+        self.reseed_interval
+    }
+
+/// Sets the time after which the generator reseeds itself with fresh entropy.
+///
+/// Reseeding happens at the end of a generation call once either the interval has elapsed
+/// since the last reseed or enough bytes have been produced.
+///
+/// # Arguments
+///
+/// * `interval` - The maximum time between two reseeds.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use horizon::nebula::Nebula;
+///
+/// let mut nebula = Nebula::new(123456789);
+/// nebula.set_reseed_interval(Duration::from_secs(10));
+/// ```
+    pub fn set_reseed_interval(&mut self, interval: Duration) {
+        self.reseed_interval = interval;
+    }
+
+
+/// Accumulates entropy into the pool before the generator is first used.
+///
+/// A fresh generator only holds its seed. Security-sensitive uses, such as generating keys or nonces
+/// from it, should warm it up first so its output also depends on several samples of the system state,
+/// gathered at different times.
+///
+/// # Arguments
+///
+/// * `events` - The number of entropy samples to gather.
+///
+/// # Errors
+///
+/// Returns an error if gathering entropy fails, see `add_entropy`.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::nebula::secured_seed;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use horizon::nebula::Nebula;
+///
+/// let mut nebula = Nebula::new(secured_seed()?);
+/// nebula.warm_up(16)?;
+/// let key_bytes = nebula.generate_random_bytes(32)?;
+/// # Ok(())
+/// # }
+/// ```
+    pub fn warm_up(&mut self, events: usize) -> Result<(), SystemTrayError> {
+        for _ in 0..events {
+            self.add_entropy()?;
+            let entropy = self.combine_entropy();
+            self.seed ^= entropy;
+        }
+
+        Ok(())
+    }
     
 /// Adds entropy to the internal pool of the `Nebula` struct.
 ///
@@ -64,7 +280,8 @@ impl Nebula {
 /// # Examples
 ///
 /// ```
-/// use your_crate::{Nebula, SystemTrayError};
+/// use horizon::nebula::Nebula;
+/// use horizon::systemtrayerror::SystemTrayError;
 ///
 /// # fn main() -> Result<(), SystemTrayError> {
 /// let nebula = Nebula::new(123456789);
@@ -75,30 +292,50 @@ impl Nebula {
 /// # }
 /// ```
     pub fn add_entropy(&self) -> Result<(), SystemTrayError> {
+        add_entropy_to(&self.pool)
+    }
 
+/// Starts a background thread calling `add_entropy` every `interval`, for long-running services.
+///
+/// The thread only shares the entropy pool with the generator, and gathers the system entropy before taking
+/// its lock like `add_entropy`, so the draws of the generator are not held up. Gathering failures are skipped
+/// until the next tick. The thread stops when the returned handle is dropped.
+///
+/// # Arguments
+///
+/// * `interval` - The time between two entropy gatherings.
+///
+/// # Example
+///
+/// ```
+/// # use std::time::Duration;
+/// # use horizon::nebula::{secured_seed, Nebula};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut nebula = Nebula::new(secured_seed()?);
+/// let _reseeder = nebula.spawn_reseeder(Duration::from_secs(30));
+///
+/// // The pool keeps receiving entropy while the service draws from it
+/// let token = nebula.generate_random_bytes(32)?;
+/// # Ok(())
+/// # }
+/// ```
+    pub fn spawn_reseeder(&self, interval: Duration) -> ReseederHandle {
+        let pool = Arc::clone(&self.pool);
+        let (stop, stopped) = mpsc::channel::<()>();
 
-        let mut pool = self.pool.lock().unwrap();
-        if pool.len() >= MAX_POOL_SIZE {
-            pool.pop_front();
-        }
+        let thread = std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let _ = add_entropy_to(&pool);
+            }
+        });
 
-        let mut entropy_sources = data_computer()?;
-        self.shuffle_array(&mut entropy_sources);
-        for source in &entropy_sources {
-            let entropy_bytes = source.to_be_bytes();
-            let mut hasher = Hasher::new();
-            hasher.update(&entropy_bytes);
-            let mut hash = [0; 64];
-            hasher.finalize_xof().fill(&mut hash);
-            pool.extend(hash.iter());
-        }
-        Ok(())
+        ReseederHandle { stop: Some(stop), thread: Some(thread) }
     }
 
     
 /// Shuffles elements of an array using a cryptographic pseudorandom number generator.
 ///
-/// This method shuffles elements of a generic array using the cryptographic pseudorandom number generator of the `Nebula` struct.
+/// This function shuffles elements of a generic array using a new `Nebula` seeded with `secured_seed`.
 ///
 /// # Arguments
 ///
@@ -106,30 +343,18 @@ impl Nebula {
 ///
 /// # Example
 ///
-/// ```
+/// ```ignore
 /// use your_crate::Nebula;
 ///
 /// let mut array = [1, 2, 3, 4, 5];
-/// let nebula = Nebula::new(123456789);
 ///
-/// // Shuffle the elements of the array using the Nebula instance
-/// nebula.shuffle_array(&mut array);
-/// ```
-    fn shuffle_array<T>(&self, array: &mut [T]) {
-        let mut rng = Nebula::new(secured_seed());
-        rng.combine_entropy();
-        let len = array.len();
-        for i in (1..len).rev() {
-            match rng.generate_bounded_number(0, i as u128) {
-                Ok(random_number) => {
-                    let j = random_number as usize;
-                    array.swap(i, j);
-                }
-                Err(err) => {
-                    eprintln!("SystemTrayError: {:?}", err);
-                }
-            }
-        }
+/// // Shuffle the elements of the array with a freshly seeded generator
+/// Nebula::shuffle_array(&mut array)?;
+/// ```
+    fn shuffle_array<T>(array: &mut [T]) -> Result<(), SystemTrayError> {
+        let mut rng = Nebula::new(secured_seed()?);
+        unbiased_shuffle(array, &mut rng);
+        Ok(())
     }
 
 /// Reseeds the internal state of the `Nebula` struct.
@@ -143,7 +368,7 @@ impl Nebula {
 ///
 /// # Example
 ///
-/// ```
+/// ```ignore
 /// use your_crate::Nebula;
 ///
 /// let mut nebula = Nebula::new(123456789);
@@ -154,8 +379,10 @@ impl Nebula {
 fn reseed(&mut self, new_seed: u128) {
     {
         let mut bytes_since_reseed = self.bytes_since_reseed.lock().unwrap();
+        // A clock moving backward saturates to zero elapsed time instead of underflowing
+        let interval_elapsed = self.clock.now().saturating_sub(self.last_reseed_time) >= self.reseed_interval.as_nanos();
 
-        if *bytes_since_reseed < RESEED_THRESHOLD {
+        if *bytes_since_reseed < RESEED_THRESHOLD && !interval_elapsed {
             return;
         }
 
@@ -178,7 +405,7 @@ fn reseed(&mut self, new_seed: u128) {
     self.seed = u128::from_be_bytes(hash_result.as_bytes()[0..16].try_into().unwrap());
 
     // Update the last reseed time
-    self.last_reseed_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    self.last_reseed_time = self.clock.now();
 
     // Clear the pool to prevent leakage of old entropy
     let mut pool = self.pool.lock().unwrap();
@@ -196,7 +423,7 @@ fn reseed(&mut self, new_seed: u128) {
 ///
 /// # Example
 ///
-/// ```
+/// ```ignore
 /// use your_crate::Nebula;
 ///
 /// let nebula = Nebula::new(123456789);
@@ -212,12 +439,11 @@ fn reseed(&mut self, new_seed: u128) {
 
         // Lock the pool and add its bytes
         let mut pool = self.pool.lock().unwrap();
-        hasher.update(&pool.make_contiguous()); // Efficiently add all bytes in the pool
+        hasher.update(pool.make_contiguous()); // Efficiently add all bytes in the pool
 
         // Add additional entropy sources
         hasher.update(&self.last_reseed_time.to_be_bytes());
-        let current_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
-        hasher.update(&current_time.to_be_bytes());
+        hasher.update(&self.clock.now().to_be_bytes());
 
         // Finalize the hash and convert the first 16 bytes to u128
         let hash_result = hasher.finalize();
@@ -234,7 +460,7 @@ fn reseed(&mut self, new_seed: u128) {
 ///
 /// # Example
 ///
-/// ```
+/// ```ignore
 /// use your_crate::Nebula;
 ///
 /// let mut nebula = Nebula::new(123456789);
@@ -243,16 +469,51 @@ fn reseed(&mut self, new_seed: u128) {
 /// let entropy = 987654321;
 /// nebula.mix_entropy(entropy);
 /// ```
-    fn mix_entropy(&mut self, entropy: u128) {
+    fn mix_entropy(&self, entropy: u128) {
         let entropy_bytes = entropy.to_be_bytes();
 
-        let mut hasher = Hasher::new();
-        hasher.update(self.pool.lock().unwrap().make_contiguous());
-        hasher.update(&entropy_bytes);
+        let mut pool = self.pool.lock().unwrap();
+        let input = [pool.make_contiguous(), &entropy_bytes[..]].concat();
 
         let mut hash = [0; 64];
-        hasher.finalize_xof().fill(&mut hash);
-        self.pool = Mutex::new(VecDeque::from(hash.to_vec()));
+        xof(&input, &mut hash);
+        *pool = VecDeque::from(hash.to_vec());
+    }
+
+/// Mixes a block read from a file or device, such as a hardware generator at `/dev/hwrng`, into the pool.
+///
+/// At most `DEVICE_BLOCK_SIZE` bytes are read, so an endless device can be given directly.
+///
+/// # Arguments
+///
+/// * `path` - The path of the entropy source.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 2 if the path cannot be opened or read. The pool is left unchanged in that case.
+///
+/// # Example
+///
+/// ```no_run
+/// # use horizon::nebula::{secured_seed, Nebula};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let nebula = Nebula::new(secured_seed()?);
+/// nebula.add_entropy_from_path("/dev/hwrng")?;
+/// # Ok(())
+/// # }
+/// ```
+    pub fn add_entropy_from_path(&self, path: impl AsRef<Path>) -> Result<(), SystemTrayError> {
+        let mut block = Vec::with_capacity(DEVICE_BLOCK_SIZE);
+        File::open(path)
+            .and_then(|file| file.take(DEVICE_BLOCK_SIZE as u64).read_to_end(&mut block))
+            .map_err(|_| SystemTrayError::new(2))?;
+
+        let mut hasher = Hasher::new_derive_key("horizon nebula device entropy");
+        hasher.update(&block);
+        block.zeroize();
+
+        self.mix_entropy(u128::from_be_bytes(hasher.finalize().as_bytes()[..16].try_into().unwrap()));
+        Ok(())
     }
 
 /// Generates a sequence of random bytes using the `Nebula` struct's internal state.
@@ -267,17 +528,25 @@ fn reseed(&mut self, new_seed: u128) {
 ///
 /// A vector containing the generated random bytes.
 ///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 12 if the repetition count test detects that the generator is stuck
+/// repeating the same byte. No bytes are returned in that case.
+///
 /// # Example
 ///
 /// ```
-/// use your_crate::Nebula;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use horizon::nebula::Nebula;
 ///
 /// let mut nebula = Nebula::new(123456789);
 ///
 /// // Generate 10 random bytes
-/// let random_bytes = nebula.generate_random_bytes(10);
+/// let random_bytes = nebula.generate_random_bytes(10)?;
+/// # Ok(())
+/// # }
 /// ```
-pub(crate) fn generate_random_bytes(&mut self, count: usize) -> Vec<u8> {
+pub fn generate_random_bytes(&mut self, count: usize) -> Result<Vec<u8>, SystemTrayError> {
     let mut random_bytes = Vec::with_capacity(count);
     let mut hasher = Hasher::new(); // Utilisez un algorithme de hachage sécurisé
 
@@ -292,6 +561,7 @@ pub(crate) fn generate_random_bytes(&mut self, count: usize) -> Vec<u8> {
         // Finalisez le hachage pour obtenir un nouvel octet aléatoire
         let hash_result = hasher.finalize();
         let random_byte = hash_result.as_bytes()[0]; // Prenez le premier octet du hachage
+        self.repetition_test.check(random_byte)?;
         random_bytes.push(random_byte);
 
         // Réinitialisez le hachage pour la prochaine itération
@@ -302,7 +572,7 @@ pub(crate) fn generate_random_bytes(&mut self, count: usize) -> Vec<u8> {
     let last_byte = random_bytes.last().copied().unwrap_or(0);
     self.reseed(last_byte as u128);
 
-    random_bytes
+    Ok(random_bytes)
 }
 
 /// Generates a 128-bit random number using the `Nebula` struct's internal state.
@@ -313,18 +583,25 @@ pub(crate) fn generate_random_bytes(&mut self, count: usize) -> Vec<u8> {
 ///
 /// A 128-bit random number.
 ///
+/// # Errors
+///
+/// Returns an error if the generator fails its repetition count test.
+///
 /// # Example
 ///
 /// ```
-/// use your_crate::Nebula;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use horizon::nebula::Nebula;
 ///
 /// let mut nebula = Nebula::new(123456789);
 ///
 /// // Generate a random number
-/// let random_number = nebula.generate_random_number();
+/// let random_number = nebula.generate_random_number()?;
+/// # Ok(())
+/// # }
 /// ```
-pub(crate) fn generate_random_number(&mut self) -> u128 {
-        let random_bytes = self.generate_random_bytes(8);
+pub fn generate_random_number(&mut self) -> Result<u128, SystemTrayError> {
+        let random_bytes = self.generate_random_bytes(8)?;
 
         let mut random_number: u128 = 0;
 
@@ -332,7 +609,7 @@ pub(crate) fn generate_random_number(&mut self) -> u128 {
             random_number = (random_number << 8) | u128::from(byte);
         }
 
-        random_number
+        Ok(random_number)
     }
 
 /// Generates a bounded random number using the `Nebula` struct's internal state.
@@ -350,12 +627,12 @@ pub(crate) fn generate_random_number(&mut self) -> u128 {
 ///
 /// # Errors
 ///
-/// An error is returned if `min` is greater than `max`.
+/// An error is returned if `min` is greater than `max`, or if the generator fails its repetition count test.
 ///
 /// # Example
 ///
 /// ```
-/// use your_crate::{Nebula, SystemTrayError};
+/// use horizon::nebula::Nebula;
 ///
 /// let mut nebula = Nebula::new(123456789);
 ///
@@ -373,9 +650,140 @@ pub(crate) fn generate_random_number(&mut self) -> u128 {
         if min > max {
             return Err(SystemTrayError::new(9));
         }
-        let random_number = self.generate_random_number();
+        let random_number = self.generate_random_number()?;
+
+        // The full `u128` range has no representable size, every number is in range
+        match (max - min).checked_add(1) {
+            Some(range) => Ok(min + random_number % range),
+            None => Ok(random_number),
+        }
+    }
+
+/// Runs a power-on self-test of the generator: the FIPS 140-2 monobit, runs and long run tests on a fresh sample.
+///
+/// Applications with compliance requirements should call it once at startup, and refuse to generate keys if it fails.
+/// The bounds are loose enough for a healthy generator to fail only rarely, but a failure is worth retrying once before giving up.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 23 if the sample fails one of the tests, or if the generator cannot produce it.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::nebula::{secured_seed, Nebula};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut nebula = Nebula::new(secured_seed()?);
+/// nebula.self_test()?;
+///
+/// let key = nebula.generate_random_bytes(64)?;
+/// # Ok(())
+/// # }
+/// ```
+    pub fn self_test(&mut self) -> Result<(), SystemTrayError> {
+        let sample = self.generate_random_bytes(SELF_TEST_SAMPLE_SIZE).map_err(|_| SystemTrayError::new(23))?;
+        check_self_test_sample(&sample)
+    }
+
+/// Writes `count` random bytes into a sink, such as a file to overwrite with random data.
+///
+/// The bytes are generated and written in chunks of `WRITE_CHUNK_SIZE`, so the output is never held in memory at once.
+///
+/// # Arguments
+///
+/// * `writer` - The sink receiving the random bytes.
+/// * `count` - The number of random bytes to write.
+///
+/// # Errors
+///
+/// Returns the error of the writer, or an error of kind `Other` wrapping the `SystemTrayError` if the generator fails.
+/// The bytes written before the error stay in the sink.
+///
+/// # Example
+///
+/// ```no_run
+/// # use horizon::nebula::{secured_seed, Nebula};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut file = std::fs::OpenOptions::new().write(true).open("secret.txt")?;
+/// let length = file.metadata()?.len();
+///
+/// Nebula::new(secured_seed()?).write_random(&mut file, length as usize)?;
+/// # Ok(())
+/// # }
+/// ```
+    pub fn write_random<W: Write>(&mut self, mut writer: W, count: usize) -> io::Result<()> {
+        let mut chunk = [0u8; WRITE_CHUNK_SIZE];
+        let mut remaining = count;
+
+        while remaining > 0 {
+            let length = remaining.min(WRITE_CHUNK_SIZE);
+            self.try_fill_bytes(&mut chunk[..length]).map_err(io::Error::other)?;
+            writer.write_all(&chunk[..length])?;
+            remaining -= length;
+        }
+
+        Ok(())
+    }
+}
+
+/// Exposes `Nebula` as a `rand_core` generator so it can drive generic algorithms such as `unbiased_shuffle`.
+impl RngCore for Nebula {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest).expect("Nebula failed to generate random bytes");
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        let random_bytes = self.generate_random_bytes(dest.len()).map_err(rand_core::Error::new)?;
+        dest.copy_from_slice(&random_bytes);
+        Ok(())
+    }
+}
+
+/// Gathers system entropy and appends its hash to `pool`, keeping at most `MAX_POOL_SIZE` bytes.
+fn add_entropy_to(pool: &Mutex<VecDeque<u8>>) -> Result<(), SystemTrayError> {
+    // Scanning the system is slow: gather and hash without the pool lock,
+    // so concurrent draws only wait for the bytes to be appended
+    let mut entropy_sources = data_computer()?;
+    #[cfg(test)]
+    GATHER_HOOK.with(|hook| if let Some(hook) = &*hook.borrow() { hook() });
+    Nebula::shuffle_array(&mut entropy_sources)?;
+
+    let mut gathered = Vec::with_capacity(entropy_sources.len() * 64);
+    for source in &entropy_sources {
+        let mut hash = [0; 64];
+        xof(&source.to_be_bytes(), &mut hash);
+        gathered.extend_from_slice(&hash);
+    }
+
+    let mut pool = pool.lock().unwrap();
+    pool.extend(gathered);
+    // Keep the newest bytes, a call adds more than one byte over the cap
+    let excess = pool.len().saturating_sub(MAX_POOL_SIZE);
+    pool.drain(..excess);
+    Ok(())
+}
+
+/// Stops the background thread started by `Nebula::spawn_reseeder` when dropped.
+pub struct ReseederHandle {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
 
-        Ok(min + (random_number % (max - min + 1)))
+impl Drop for ReseederHandle {
+    /// Wakes the thread up and waits for it, at most for the end of an ongoing gathering.
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
     }
 }
 
@@ -399,11 +807,14 @@ pub(crate) fn generate_random_number(&mut self) -> u128 {
 ///
 /// # Errors
 ///
-/// An error is returned if the total disk usage by all processes is zero, indicating a failure to retrieve disk usage data.
+/// An error is returned if too few system sources are available and the OS CSPRNG cannot replace the missing ones
+/// (see `complete_sources`). An empty process list is retried `MAX_GATHER_ATTEMPTS` times before the disk usage is
+/// counted as missing. The system snapshot is cached and only refreshed once the refresh interval has elapsed
+/// (see `set_entropy_refresh_interval`).
 ///
 /// # Example
 ///
-/// ```
+/// ```ignore
 /// use your_crate::{data_computer, SystemTrayError};
 ///
 /// // Gather system-related data for entropy generation
@@ -417,93 +828,384 @@ pub(crate) fn generate_random_number(&mut self) -> u128 {
 /// }
 /// ```
 fn data_computer() -> Result<[u128; 10], SystemTrayError> {
-    let mut sys = System::new();
-    sys.refresh_memory();
+    if let Some(sources) = entropy_override() {
+        return Ok(sources);
+    }
 
-    let mut s = System::new();
-    s.refresh_processes(ProcessesToUpdate::All);
+    let refresh_interval = *REFRESH_INTERVAL.lock().unwrap();
+    let cached = SYSTEM_SNAPSHOT.lock().unwrap().clone();
 
-    let total_memory = sys.total_memory();
-    let used_memory = sys.used_memory();
-    let total_swap = sys.total_swap();
+    // Gather outside the lock so concurrent callers keep using the previous snapshot meanwhile
+    let snapshot = match cached {
+        Some(snapshot) if snapshot.refreshed_at.elapsed() < refresh_interval => snapshot,
+        cached => {
+            let snapshot = Arc::new(gather_snapshot(cached.map_or(1, |snapshot| snapshot.refresh_count + 1)));
+            *SYSTEM_SNAPSHOT.lock().unwrap() = Some(Arc::clone(&snapshot));
+            snapshot
+        },
+    };
+
+    let s = &snapshot.system;
+
+    let total_memory = s.total_memory();
+    let used_memory = s.used_memory();
+    let total_swap = s.total_swap();
     let cpu = s.cpus().len() as u128;
     let uptime = System::uptime() as u128;
     let boot_time= System::boot_time() as u128;
 
-    let networks = Networks::new_with_refreshed_list();
-    let network_data: u128 = calculate_network_data(&networks);
+    let network_data: u128 = calculate_network_data(&snapshot.networks);
 
     let pid_set: HashSet<&Pid> = s.processes().keys().collect();
     let pid_disk_usage: u128 = pid_set.into_par_iter()
-        .map(|&pid| calculate_disk_usage(&s, pid))
+        .map(|&pid| calculate_disk_usage(s, pid))
         .sum();
 
-    let time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_nanos();
+    let time = current_time();
 
     let pid = std::process::id();
 
-    Ok([time, pid.into(), total_memory as u128, used_memory as u128, total_swap as u128, pid_disk_usage, uptime, boot_time, network_data, cpu])
+    let sources = [time, pid.into(), total_memory as u128, used_memory as u128, total_swap as u128, pid_disk_usage, uptime, boot_time, network_data, cpu];
+    complete_sources(sources, try_os_random)
 }
 
-fn calculate_network_data(network: &Networks) -> u128 {
-    network.par_iter()
-        .map(|(_, network)| {
-            network.received() as u128
-                + network.total_received() as u128
-                + network.transmitted() as u128
-                + network.total_transmitted() as u128
-                + network.packets_received() as u128
-                + network.total_packets_received() as u128
-                + network.packets_transmitted() as u128
-                + network.total_packets_transmitted() as u128
-                + network.errors_on_received() as u128
-                + network.total_errors_on_received() as u128
-                + network.errors_on_transmitted() as u128
-        })
-        .sum()
-}
+/// Fills the system sources that are missing with values from `backstop`.
+///
+/// Each source is optional: a locked-down system may list no process or no network, leaving those sources at 0.
+/// Every missing source after the time and the process id is replaced by a fresh value of `backstop`, the OS CSPRNG
+/// outside of tests.
+///
+/// # Errors
+///
+/// Returns the code 8 error if the backstop is unavailable and fewer than `MIN_SYSTEM_SOURCES` system sources are
+/// non-zero, since the seed would then be guessable.
+fn complete_sources(mut sources: [u128; 10], mut backstop: impl FnMut() -> Option<u128>) -> Result<[u128; 10], SystemTrayError> {
+    let available = sources[2..].iter().filter(|&&source| source != 0).count();
 
-fn calculate_disk_usage(sys: &System, pid: Pid) -> u128 {
-    sys.process(pid)
-        .map_or(0, |process| process.disk_usage().total_read_bytes as u128)
+    for source in sources[2..].iter_mut().filter(|source| **source == 0) {
+        match backstop() {
+            Some(value) => *source = value,
+            None if available < MIN_SYSTEM_SOURCES => return Err(SystemTrayError::new(8)),
+            None => break,
+        }
+    }
+
+    Ok(sources)
 }
 
-/// Generates a secured seed for cryptographic operations.
-///
-/// This function generates a secured seed by combining system-related data and current system time.
-///
-/// # Returns
+/// Returns the current system time in nanoseconds since the UNIX epoch.
 ///
-/// A secured seed for cryptographic operations.
+/// In tests, the time is taken from the entropy override when one is set, so that the generator is reproducible.
+/// A system time before the epoch is read as 0.
+fn current_time() -> u128 {
+    if let Some(sources) = entropy_override() {
+        return sources[0];
+    }
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos())
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Fixed entropy sources returned by `data_computer` on the current test thread, the first one also being used as the time.
+    /// The OS randomness is disabled while an override is set.
+    static ENTROPY_OVERRIDE: std::cell::Cell<Option<[u128; 10]>> = const { std::cell::Cell::new(None) };
+}
+
+/// Replaces the system entropy sources with fixed values on the current test thread.
 ///
-/// # Panics
+/// This makes the tests depending on `data_computer` deterministic and independent of the machine they run on.
+///
+/// # Arguments
+///
+/// * `sources` - The values returned by `data_computer`, or `None` to read the system again.
+#[cfg(test)]
+pub(crate) fn set_entropy_override(sources: Option<[u128; 10]>) {
+    ENTROPY_OVERRIDE.with(|entropy_override| entropy_override.set(sources));
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Called by `add_entropy_to` on the current test thread once the sources are gathered, before the pool is locked.
+    static GATHER_HOOK: std::cell::RefCell<Option<Box<dyn Fn()>>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Runs `hook` in the middle of every entropy gathering on the current test thread, to hold a gathering in progress.
+///
+/// # Arguments
+///
+/// * `hook` - The function to call, or `None` to remove it.
+#[cfg(test)]
+pub(crate) fn set_gather_hook(hook: Option<Box<dyn Fn()>>) {
+    GATHER_HOOK.with(|gather_hook| *gather_hook.borrow_mut() = hook);
+}
+
+#[cfg(feature = "fuzzing")]
+/// Fixed entropy sources set by `set_fuzzing_seed`, for every thread.
+static FUZZING_SOURCES: Mutex<Option<[u128; 10]>> = Mutex::new(None);
+
+/// Replaces every source of randomness of the crate with values derived from `seed`, for reproducible fuzzing.
+///
+/// While a seed is set, the system entropy, the time and the OS randomness are replaced on every thread,
+/// so `secured_seed`, `Nebula` and everything built on them, such as the random stars of `encrypt3` or the padding
+/// of `encrypt_file_aligned`, give the same output for the same seed and input. The generator is then predictable:
+/// this is only available with the `fuzzing` feature, which must never be enabled in a release build.
+///
+/// As the override is global, tests running in parallel with it see deterministic randomness too.
+///
+/// # Arguments
+///
+/// * `seed` - The seed given by the fuzzer, or `None` to use the real entropy sources again.
+///
+/// # Example
+///
+/// ```ignore
+/// // fuzz/fuzz_targets/encrypt3.rs, built with `features = ["fuzzing"]`
+/// fuzz_target!(|input: (u64, Vec<u8>)| {
+///     horizon::nebula::set_fuzzing_seed(Some(input.0));
+///     if let Ok(cipher_text) = encrypt3(input.1, &key1, &key2) {
+///         assert_eq!(decrypt3(cipher_text, &key1, &key2).unwrap(), input.1);
+///     }
+/// });
+/// ```
+#[cfg(feature = "fuzzing")]
+pub fn set_fuzzing_seed(seed: Option<u64>) {
+    let sources = seed.map(|seed| {
+        let mut rng = XofRng::new(&seed.to_be_bytes());
+        std::array::from_fn(|_| (rng.next_u64() as u128) << 64 | rng.next_u64() as u128)
+    });
+
+    *FUZZING_SOURCES.lock().unwrap() = sources;
+}
+
+/// Returns the fixed entropy sources replacing the system ones, if a test or `set_fuzzing_seed` set them.
+fn entropy_override() -> Option<[u128; 10]> {
+    #[cfg(test)]
+    if let Some(sources) = ENTROPY_OVERRIDE.with(|entropy_override| entropy_override.get()) {
+        return Some(sources);
+    }
+
+    #[cfg(feature = "fuzzing")]
+    if let Some(sources) = *FUZZING_SOURCES.lock().unwrap() {
+        return Some(sources);
+    }
+
+    None
+}
+
+/// Sets how long the cached system snapshot used by `data_computer` stays valid.
+///
+/// Scanning every process is expensive, so entropy gathering reuses the same snapshot for
+/// `interval` before refreshing it. The time-based sources are always read fresh.
+///
+/// # Arguments
+///
+/// * `interval` - The time after which the snapshot is refreshed.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use horizon::nebula::set_entropy_refresh_interval;
+///
+/// set_entropy_refresh_interval(Duration::from_secs(5));
+/// ```
+pub fn set_entropy_refresh_interval(interval: Duration) {
+    *REFRESH_INTERVAL.lock().unwrap() = interval;
+}
+
+/// Reads a new system snapshot, retrying while the process list comes back empty.
+///
+/// An empty process list is treated as transient: the read is retried after a growing delay with a random jitter,
+/// then gathering goes on without the processes.
+fn gather_snapshot(refresh_count: usize) -> SystemSnapshot {
+    let mut snapshot = SystemSnapshot::new(refresh_count);
+
+    for attempt in 1..MAX_GATHER_ATTEMPTS {
+        if !snapshot.system.processes().is_empty() {
+            break;
+        }
+        let jitter = try_os_random().map_or(0, |value| (value % GATHER_RETRY_DELAY_MS as u128) as u64);
+        std::thread::sleep(Duration::from_millis(GATHER_RETRY_DELAY_MS * attempt as u64 + jitter));
+        snapshot.system.refresh_processes(ProcessesToUpdate::All);
+    }
+
+    snapshot
+}
+
+/// System information cached between calls to `data_computer`.
+struct SystemSnapshot {
+    system: System,
+    networks: Networks,
+    refreshed_at: Instant,
+    refresh_count: usize,
+}
+
+impl SystemSnapshot {
+    fn new(refresh_count: usize) -> Self {
+        let mut system = System::new();
+        system.refresh_memory();
+        system.refresh_processes(ProcessesToUpdate::All);
+
+        SystemSnapshot {
+            system,
+            networks: Networks::new_with_refreshed_list(),
+            refreshed_at: Instant::now(),
+            refresh_count,
+        }
+    }
+}
+
+fn calculate_network_data(network: &Networks) -> u128 {
+    network.par_iter()
+        .map(|(_, network)| {
+            network.received() as u128
+                + network.total_received() as u128
+                + network.transmitted() as u128
+                + network.total_transmitted() as u128
+                + network.packets_received() as u128
+                + network.total_packets_received() as u128
+                + network.packets_transmitted() as u128
+                + network.total_packets_transmitted() as u128
+                + network.errors_on_received() as u128
+                + network.total_errors_on_received() as u128
+                + network.errors_on_transmitted() as u128
+        })
+        .sum()
+}
+
+fn calculate_disk_usage(sys: &System, pid: Pid) -> u128 {
+    sys.process(pid)
+        .map_or(0, |process| process.disk_usage().total_read_bytes as u128)
+}
+
+/// Number of bytes drawn by `Nebula::self_test`, the 20 000 bits of the FIPS 140-2 tests.
+const SELF_TEST_SAMPLE_SIZE: usize = 2500;
+
+/// Bounds, exclusive, of the number of set bits in the sample.
+const SELF_TEST_MONOBIT_BOUNDS: (usize, usize) = (9725, 10275);
+
+/// Bounds, inclusive, of the number of runs of length 1 to 5 and 6 or more, for each bit value.
+const SELF_TEST_RUN_BOUNDS: [(usize, usize); 6] = [(2315, 2685), (1114, 1386), (527, 723), (240, 384), (103, 209), (103, 209)];
+
+/// Length from which a run fails the long run test.
+const SELF_TEST_LONG_RUN: usize = 26;
+
+/// Runs the monobit, runs and long run tests of FIPS 140-2 on a 20 000 bit sample, returning code 23 if one fails.
+fn check_self_test_sample(sample: &[u8]) -> Result<(), SystemTrayError> {
+    let bits: Vec<bool> = sample.iter().flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1)).collect();
+
+    let ones = bits.iter().filter(|&&bit| bit).count();
+    if ones <= SELF_TEST_MONOBIT_BOUNDS.0 || ones >= SELF_TEST_MONOBIT_BOUNDS.1 {
+        return Err(SystemTrayError::new(23));
+    }
+
+    // runs[bit][length - 1], with the last slot counting every run of 6 or more
+    let mut runs = [[0usize; 6]; 2];
+    for run in bits.chunk_by(|a, b| a == b) {
+        if run.len() >= SELF_TEST_LONG_RUN {
+            return Err(SystemTrayError::new(23));
+        }
+        runs[run[0] as usize][run.len().min(6) - 1] += 1;
+    }
+
+    let runs_in_bounds = runs.iter().all(|counts| {
+        counts.iter().zip(SELF_TEST_RUN_BOUNDS).all(|(&count, (min, max))| (min..=max).contains(&count))
+    });
+    if !runs_in_bounds {
+        return Err(SystemTrayError::new(23));
+    }
+
+    Ok(())
+}
+
+/// Minimum number of set bits out of 128 for a seed to be acceptable, and symmetrically of unset bits.
+const MIN_SEED_BIT_BALANCE: u32 = 32;
+
+/// Minimum number of distinct bytes out of 16 for a seed to be acceptable.
+const MIN_SEED_DISTINCT_BYTES: usize = 8;
+
+/// The quality of a seed as estimated by `seed_quality`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedQuality {
+    /// The seed is obviously weak (0, 1, a repeated byte, a small number...) and should not be used.
+    Weak,
+    /// The seed looks random. This cannot prove that it is unpredictable.
+    Acceptable,
+}
+
+/// Estimates whether a seed looks random enough to be given to `Nebula::new`.
+///
+/// A random seed has about as many set bits as unset bits and few repeated bytes,
+/// so a seed far from either is rated `SeedQuality::Weak`.
 ///
-/// Panics if the current system time goes backwards.
+/// # Arguments
+///
+/// * `seed` - The seed to check.
+///
+/// # Returns
+///
+/// The estimated `SeedQuality` of `seed`.
 ///
 /// # Example
 ///
 /// ```
-/// use your_crate::secured_seed;
+/// use horizon::nebula::{seed_quality, SeedQuality};
+///
+/// assert_eq!(seed_quality(0), SeedQuality::Weak);
+/// ```
+pub fn seed_quality(seed: u128) -> SeedQuality {
+    let ones = seed.count_ones();
+    let distinct_bytes = seed.to_be_bytes().iter().collect::<HashSet<_>>().len();
+
+    if ones < MIN_SEED_BIT_BALANCE || 128 - ones < MIN_SEED_BIT_BALANCE || distinct_bytes < MIN_SEED_DISTINCT_BYTES {
+        SeedQuality::Weak
+    } else {
+        SeedQuality::Acceptable
+    }
+}
+
+/// Generates a secured seed for cryptographic operations.
+///
+/// This function generates a secured seed by combining system-related data and current system time.
+/// With the `getrandom` feature (enabled by default), the result is XORed with bytes from the OS CSPRNG,
+/// so a weak system-metric source is backstopped by the operating system.
+///
+/// # Returns
+///
+/// A secured seed for cryptographic operations.
+///
+/// # Errors
+///
+/// Returns the error of `data_computer` when too few entropy sources are available, or the error of `kdfwagen`.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use horizon::nebula::secured_seed;
 ///
 /// // Generate a secured seed for cryptographic operations
-/// let seed = secured_seed();
+/// let seed = secured_seed()?;
+/// # Ok(())
+/// # }
 /// ```
-pub fn secured_seed() -> u128 {
-    let actual_time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_nanos();
+pub fn secured_seed() -> Result<u128, SystemTrayError> {
+    secured_seed_with(os_random)
+}
 
-    let context_bytes: Vec<u8> = data_computer()
-        .unwrap()
+/// Computes `secured_seed`, XORing the result with the value of `backstop` instead of always the OS CSPRNG.
+fn secured_seed_with(backstop: impl FnOnce() -> u128) -> Result<u128, SystemTrayError> {
+    let actual_time = current_time();
+
+    let context_bytes: Vec<u8> = data_computer()?
         .par_iter()
         .flat_map(|&x| x.to_be_bytes())
         .collect();
 
-    let key = kdfwagen(&context_bytes, &actual_time.to_be_bytes(), 10);
+    let key = kdfwagen(&context_bytes, &actual_time.to_be_bytes(), 10)?;
     let key = key.expose_secret();
 
     let (part1, part2): (&[u8], &[u8]) = key.split_at(256);
@@ -511,12 +1213,34 @@ pub fn secured_seed() -> u128 {
     let sum1: u128 = part1.par_iter().map(|&x| x as u128).sum();
     let sum2: u128 = part2.par_iter().map(|&x| x as u128).sum();
 
-    sum1.wrapping_mul(sum2)
+    Ok(sum1.wrapping_mul(sum2) ^ backstop())
+}
+
+/// Reads 16 bytes from the OS CSPRNG, or 0 if it is unavailable.
+fn os_random() -> u128 {
+    try_os_random().unwrap_or(0)
+}
+
+/// Reads 16 bytes from the OS CSPRNG, or `None` if it is unavailable or the entropy is overridden.
+#[cfg(feature = "getrandom")]
+fn try_os_random() -> Option<u128> {
+    if entropy_override().is_some() {
+        return None;
+    }
+
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).ok().map(|()| u128::from_be_bytes(bytes))
+}
+
+#[cfg(not(feature = "getrandom"))]
+fn try_os_random() -> Option<u128> {
+    None
 }
 
 /// Shuffles the elements of a slice.
 ///
 /// This function shuffles the elements of a slice using a secured seed for randomness.
+/// The index is reduced with a modulo, which slightly favours the lowest indices.
 ///
 /// # Arguments
 ///
@@ -525,27 +1249,36 @@ pub fn secured_seed() -> u128 {
 /// # Example
 ///
 /// ```
-/// use your_crate::shuffle;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # #![allow(deprecated)]
+/// use horizon::nebula::shuffle;
 ///
 /// // Create a vector of integers
 /// let mut numbers = vec![1, 2, 3, 4, 5];
 ///
 /// // Shuffle the vector
-/// shuffle(&mut numbers);
+/// shuffle(&mut numbers)?;
 ///
 /// // Now `numbers` contains shuffled elements
+/// # Ok(())
+/// # }
 /// ```
-pub fn shuffle<T>(items: &mut [T]) {
+#[deprecated(note = "biased by the modulo reduction, use `unbiased_shuffle` instead")]
+pub fn shuffle<T>(items: &mut [T]) -> Result<(), SystemTrayError> {
     let len = items.len();
     for i in (1..len).rev() {
-        let j = (secured_seed() as usize) % (i + 1);
+        let j = (secured_seed()? as usize) % (i + 1);
         items.swap(i, j);
     }
+
+    Ok(())
 }
 
 /// Shuffles the elements of a slice with a specified seed.
 ///
 /// This function shuffles the elements of a slice using a specified seed for randomness.
+/// The permutation is biased, but the encryption `characters` table is built with it, so changing it
+/// would change every ciphertext. Use `unbiased_shuffle` for anything that is not tied to the cipher format.
 ///
 /// # Arguments
 ///
@@ -555,7 +1288,7 @@ pub fn shuffle<T>(items: &mut [T]) {
 /// # Example
 ///
 /// ```
-/// use your_crate::seeded_shuffle;
+/// use horizon::nebula::seeded_shuffle;
 ///
 /// // Create a vector of integers
 /// let mut numbers = vec![1, 2, 3, 4, 5];
@@ -573,7 +1306,157 @@ pub fn seeded_shuffle<T>(items: &mut [T], seed: usize) {
     }
 }
 
+/// Draws a uniformly distributed index in `0..bound`.
+///
+/// This function uses rejection sampling: the raw 64-bit values that would make the modulo reduction
+/// favour some indices are discarded and drawn again.
+///
+/// # Arguments
+///
+/// * `rng` - The random number generator to draw from.
+/// * `bound` - The exclusive upper bound of the index.
+///
+/// # Returns
+///
+/// An index in `0..bound`.
+///
+/// # Panics
+///
+/// Panics if `bound` is 0.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::nebula::secured_seed;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use horizon::nebula::{gen_index, Nebula};
+///
+/// let mut rng = Nebula::new(secured_seed()?);
+/// let index = gen_index(&mut rng, 10);
+/// # Ok(())
+/// # }
+/// ```
+pub fn gen_index<R: RngCore>(rng: &mut R, bound: usize) -> usize {
+    assert!(bound > 0, "bound must be greater than 0");
+
+    let bound = bound as u64;
+    // 2^64 mod bound: the values below it are the ones making the modulo biased
+    let threshold = bound.wrapping_neg() % bound;
+
+    loop {
+        let value = rng.next_u64();
+        if value >= threshold {
+            return (value % bound) as usize;
+        }
+    }
+}
+
+/// Shuffles the elements of a slice without bias.
+///
+/// This function implements the Fisher-Yates shuffle, drawing each swap index with `gen_index`
+/// so that every permutation is equally likely.
+///
+/// # Arguments
+///
+/// * `items` - A mutable reference to a slice of elements that need to be shuffled.
+/// * `rng` - The random number generator used for the shuffle.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::nebula::secured_seed;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use horizon::nebula::{unbiased_shuffle, Nebula};
+///
+/// let mut numbers = vec![1, 2, 3, 4, 5];
+/// let mut rng = Nebula::new(secured_seed()?);
+///
+/// unbiased_shuffle(&mut numbers, &mut rng);
+/// # Ok(())
+/// # }
+/// ```
+pub fn unbiased_shuffle<T, R: RngCore>(items: &mut [T], rng: &mut R) {
+    for i in (1..items.len()).rev() {
+        let j = gen_index(rng, i + 1);
+        items.swap(i, j);
+    }
+}
+
+/// Returns the permutation a seeded `unbiased_shuffle` applies to `len` elements, without moving any data.
+///
+/// Element `i` of the result is the original index of the element that the shuffle moves to position `i`:
+/// `shuffled[i] = items[permutation[i]]` gives the same order as shuffling `items` with `unbiased_shuffle`
+/// and `XofRng::new(&seed.to_be_bytes())`, so several parallel arrays can be reordered consistently, or lazily.
+///
+/// # Arguments
+///
+/// * `len` - The number of elements.
+/// * `seed` - The seed of the shuffle.
+///
+/// # Example
+///
+/// ```
+/// # use horizon::nebula::permutation;
+/// # let names = ["Ada", "Alan", "Grace"];
+/// # let ages = [36u32, 41, 85];
+/// let order = permutation(names.len(), 42);
+/// let shuffled_names: Vec<&str> = order.iter().map(|&i| names[i]).collect();
+/// let shuffled_ages: Vec<u32> = order.iter().map(|&i| ages[i]).collect();
+/// ```
+pub fn permutation(len: usize, seed: u64) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..len).collect();
+    unbiased_shuffle(&mut indices, &mut XofRng::new(&seed.to_be_bytes()));
+    indices
+}
+
+/// A deterministic generator reading the BLAKE3 extendable output of a seed.
+///
+/// Unlike `Nebula`, which keeps mixing in system entropy, the same seed always gives the same stream,
+/// which is what keyed tables and seeded shuffles need to be rebuilt for decryption.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::nebula::{unbiased_shuffle, XofRng};
+/// # let seed = 42u64;
+/// # let mut characters: Vec<u8> = (0..=255).collect();
+/// let mut rng = XofRng::new(&seed.to_be_bytes());
+/// unbiased_shuffle(&mut characters, &mut rng);
+/// ```
+pub struct XofRng {
+    reader: blake3::OutputReader,
+}
+
+impl XofRng {
+    /// Creates a generator whose output is derived from `seed`.
+    pub fn new(seed: &[u8]) -> Self {
+        let mut hasher = Hasher::new_derive_key("horizon xof rng");
+        hasher.update(seed);
+        XofRng { reader: hasher.finalize_xof() }
+    }
+}
+
+impl RngCore for XofRng {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.reader.fill(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
 ////////// function test
+#[cfg(test)]
 fn monobit_test(sequence: &[u8]) -> bool {
     let total_bits = sequence.len() * 8;
     let mut one_bits: i32 = 0;
@@ -603,11 +1486,13 @@ mod tests {
 
     #[test]
     fn test_add_entropy() {
+        set_entropy_override(Some([1_700_000_000_000_000_000, 42, 16, 8, 4, 1234, 3600, 1_700_000_000, 99, 8]));
         let rng = Nebula::new(12345);
         let initial_state = rng.pool.lock().unwrap().clone();
         let _ = rng.add_entropy();
         println!("{:?} {:?}", initial_state, rng.pool.lock().unwrap());
         assert_ne!(*rng.pool.lock().unwrap(), initial_state, "L'ajout d'entropie n'a pas modifié l'état du générateur");
+        set_entropy_override(None);
     }
 
     #[test]
@@ -616,26 +1501,353 @@ mod tests {
         let initial_state = rng.pool.lock().unwrap().clone();
         // Generate enough random bytes to meet the reseed threshold
         for _ in 0..(RESEED_THRESHOLD / 8) {
-            rng.generate_random_bytes(8);
+            rng.generate_random_bytes(8).unwrap();
         }
         rng.reseed(67890);
         assert_ne!(*rng.pool.lock().unwrap(), initial_state, "La méthode reseed n'a pas modifié l'état du générateur");
     }
 
+    #[test]
+    fn test_time_based_reseed() {
+        let mut rng = Nebula::new(12345);
+        assert_eq!(rng.reseed_interval(), DEFAULT_RESEED_INTERVAL);
+
+        rng.generate_random_bytes(8).unwrap();
+        assert_eq!(rng.seed, 12345, "The generator reseeded before the interval elapsed");
+
+        rng.set_reseed_interval(Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(5));
+        rng.generate_random_bytes(8).unwrap();
+        assert_ne!(rng.seed, 12345, "The generator did not reseed once the interval elapsed");
+    }
+
+    /// A clock the tests move by hand.
+    struct ManualClock(std::sync::Arc<Mutex<u128>>);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> u128 {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_manual_clock_reseed() {
+        set_entropy_override(Some([1_700_000_000_000_000_000, 42, 16, 8, 4, 1234, 3600, 1_700_000_000, 99, 8]));
+        let time = std::sync::Arc::new(Mutex::new(1_000_000_000_000));
+        let mut rng = Nebula::with_clock(12345, ManualClock(time.clone()));
+        rng.set_reseed_interval(Duration::from_secs(10));
+
+        *time.lock().unwrap() += Duration::from_secs(9).as_nanos();
+        rng.generate_random_bytes(8).unwrap();
+        assert_eq!(rng.seed, 12345, "The generator reseeded before the interval elapsed");
+
+        // A backward jump counts as no elapsed time
+        *time.lock().unwrap() = 1;
+        rng.generate_random_bytes(8).unwrap();
+        assert_eq!(rng.seed, 12345, "The generator reseeded after the clock moved backward");
+
+        *time.lock().unwrap() = 1_000_000_000_000 + Duration::from_secs(10).as_nanos();
+        rng.generate_random_bytes(8).unwrap();
+        assert_ne!(rng.seed, 12345, "The generator did not reseed once the interval elapsed");
+
+        set_entropy_override(None);
+    }
+
+    #[test]
+    fn test_warm_up() {
+        set_entropy_override(Some([1_700_000_000_000_000_000, 42, 16, 8, 4, 1234, 3600, 1_700_000_000, 99, 8]));
+        let mut cold = Nebula::new(12345);
+        let mut warm = Nebula::new(12345);
+
+        warm.warm_up(4).unwrap();
+        assert!(warm.pool.lock().unwrap().len() > cold.pool.lock().unwrap().len(), "The pool did not grow during warm-up");
+        assert_ne!(warm.seed, cold.seed);
+        assert_ne!(warm.generate_random_bytes(32).unwrap(), cold.generate_random_bytes(32).unwrap());
+
+        set_entropy_override(None);
+    }
+
+    #[test]
+    fn test_add_entropy_does_not_stall_draws() {
+        let nebula = Arc::new(Nebula::new(123456789));
+        let gathering = Arc::new(std::sync::Barrier::new(2));
+
+        // Hold the gathering between two barriers: the first tells it started, the second releases it
+        let gatherer = {
+            let nebula = Arc::clone(&nebula);
+            let gathering = Arc::clone(&gathering);
+            std::thread::spawn(move || {
+                set_entropy_override(Some([1_700_000_000_000_000_000, 42, 16, 8, 4, 1234, 3600, 1_700_000_000, 99, 8]));
+                set_gather_hook(Some(Box::new(move || {
+                    gathering.wait();
+                    gathering.wait();
+                })));
+                nebula.add_entropy().unwrap();
+            })
+        };
+        gathering.wait();
+
+        let (done, finished) = mpsc::channel();
+        let drawer = {
+            let nebula = Arc::clone(&nebula);
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    nebula.combine_entropy();
+                }
+                let _ = done.send(());
+            })
+        };
+        let drawn = finished.recv_timeout(Duration::from_secs(10));
+
+        gathering.wait();
+        gatherer.join().unwrap();
+        drawer.join().unwrap();
+        assert!(drawn.is_ok(), "The draws waited for the entropy gathering");
+    }
+
+    #[test]
+    fn test_spawn_reseeder() {
+        let nebula = Nebula::new(123456789);
+        let initial = nebula.pool.lock().unwrap().clone();
+
+        let reseeder = nebula.spawn_reseeder(Duration::from_millis(10));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while *nebula.pool.lock().unwrap() == initial {
+            assert!(Instant::now() < deadline, "The reseeder never added entropy");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(Arc::strong_count(&nebula.pool), 2);
+
+        // Dropping the handle joins the thread, which releases its share of the pool
+        drop(reseeder);
+        assert_eq!(Arc::strong_count(&nebula.pool), 1);
+        let stopped = nebula.pool.lock().unwrap().clone();
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(*nebula.pool.lock().unwrap(), stopped);
+    }
+
+    #[test]
+    fn test_permutation() {
+        let items: Vec<u32> = (100..1100).collect();
+        let order = permutation(items.len(), 42);
+
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..items.len()).collect::<Vec<usize>>());
+        assert_eq!(order, permutation(items.len(), 42));
+        assert_ne!(order, permutation(items.len(), 43));
+
+        let shuffled: Vec<u32> = order.iter().map(|&i| items[i]).collect();
+        let mut expected = items.clone();
+        unbiased_shuffle(&mut expected, &mut XofRng::new(&42u64.to_be_bytes()));
+        assert_eq!(shuffled, expected);
+
+        // The inverse permutation puts every element back
+        let mut inverse = vec![0; order.len()];
+        for (position, &original) in order.iter().enumerate() {
+            inverse[original] = position;
+        }
+        let restored: Vec<u32> = inverse.iter().map(|&i| shuffled[i]).collect();
+        assert_eq!(restored, items);
+
+        assert!(permutation(0, 42).is_empty());
+    }
+
+    #[test]
+    fn test_xof_rng_deterministic() {
+        let mut first = XofRng::new(b"seed");
+        let mut second = XofRng::new(b"seed");
+        let mut other = XofRng::new(b"other seed");
+
+        let values: Vec<u64> = (0..4).map(|_| first.next_u64()).collect();
+        assert_eq!(values, (0..4).map(|_| second.next_u64()).collect::<Vec<u64>>());
+        assert_ne!(values, (0..4).map(|_| other.next_u64()).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_secured_seed_os_backstop() {
+        // All-zero system metrics must not make seeding panic
+        set_entropy_override(Some([0; 10]));
+        let weak = secured_seed_with(|| 0).unwrap();
+        let backstop = 0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210;
+        let backstopped = secured_seed_with(|| backstop).unwrap();
+        set_entropy_override(None);
+
+        assert_eq!(weak ^ backstopped, backstop, "The backstop was not XORed into the seed");
+    }
+
+    #[test]
+    #[cfg(feature = "getrandom")]
+    fn test_os_random() {
+        assert_ne!(os_random(), 0, "The OS CSPRNG is unavailable");
+        assert_ne!(os_random(), os_random(), "The OS CSPRNG returned the same bytes twice");
+    }
+
+    #[test]
+    fn test_complete_sources_without_networks_and_processes() {
+        // A locked-down system: no swap, no process disk usage and no network data
+        let sources = [1_700_000_000_000_000_000, 4242, 8 << 30, 0, 0, 0, 3600, 1_700_000_000, 0, 0];
+
+        let mut backstop = 0u128;
+        let completed = complete_sources(sources, || {
+            backstop += 1;
+            Some(backstop.wrapping_mul(0x9E37_79B9_7F4A_7C15_F39C_C060_5CED_C835))
+        }).unwrap();
+
+        assert_eq!(backstop, 5, "Every missing source must be replaced by the backstop");
+        assert!(completed.iter().all(|&source| source != 0), "A missing source was left at 0");
+        assert_eq!(completed[..3], sources[..3], "An available source was replaced");
+
+        let mut nebula = Nebula::new(completed.iter().fold(0, |seed, &source| seed ^ source));
+        assert_ne!(nebula.generate_random_number().unwrap(), nebula.generate_random_number().unwrap());
+
+        assert_eq!(complete_sources(sources, || None).unwrap_err().code, 8);
+        let healthy = [1, 2, 3, 4, 0, 6, 7, 8, 0, 10];
+        assert_eq!(complete_sources(healthy, || None).unwrap(), healthy);
+    }
+
+    #[test]
+    fn test_generate_bounded_number_full_range() {
+        set_entropy_override(Some([1_700_000_000_000_000_000, 42, 16, 8, 4, 1234, 3600, 1_700_000_000, 99, 8]));
+        let mut nebula = Nebula::new(12345);
+
+        assert!(nebula.generate_bounded_number(0, u128::MAX).is_ok());
+        assert_eq!(nebula.generate_bounded_number(u128::MAX, u128::MAX).unwrap(), u128::MAX);
+        assert!(nebula.generate_bounded_number(1, 0).is_err());
+
+        set_entropy_override(None);
+    }
+
+    #[test]
+    fn test_seed_quality() {
+        assert_eq!(seed_quality(0), SeedQuality::Weak);
+        assert_eq!(seed_quality(1), SeedQuality::Weak);
+        assert_eq!(seed_quality(u128::MAX), SeedQuality::Weak);
+        assert_eq!(seed_quality(0x0101_0101_0101_0101_0101_0101_0101_0101), SeedQuality::Weak);
+        assert_eq!(seed_quality(0x1234_5678), SeedQuality::Weak);
+        assert_eq!(seed_quality(0x3c9f_07e2_5ad1_846b_f0c3_29ae_7d15_b648), SeedQuality::Acceptable);
+    }
+
+    #[test]
+    #[cfg(feature = "getrandom")]
+    fn test_secured_seed_quality() {
+        assert_eq!(seed_quality(secured_seed().unwrap()), SeedQuality::Acceptable);
+    }
+
+    #[test]
+    fn test_seed_from_reader() {
+        let path = std::env::temp_dir().join(format!("horizon_seed_{}", std::process::id()));
+
+        std::fs::write(&path, b"secret seed material").unwrap();
+        let first = Nebula::seed_from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+        let second = Nebula::seed_from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+
+        std::fs::write(&path, b"secret seed materiaL").unwrap();
+        let changed = Nebula::seed_from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(first, second, "The same file gave different seeds");
+        assert_ne!(first, changed, "A changed file gave the same seed");
+    }
+
+    #[test]
+    fn test_self_test() {
+        // Fixed entropy and time make the sample, and so the result, reproducible
+        set_entropy_override(Some([1_700_000_000_000_000_000, 42, 16, 8, 4, 1234, 3600, 1_700_000_000, 99, 8]));
+        let clock = ManualClock(std::sync::Arc::new(Mutex::new(1_700_000_000_000_000_000)));
+        let result = Nebula::with_clock(12345, clock).self_test();
+        set_entropy_override(None);
+        assert!(result.is_ok(), "A healthy generator failed the self-test");
+
+        // Stuck generators: constant output fails the monobit test, alternating bits the runs test
+        assert_eq!(check_self_test_sample(&[0x00; SELF_TEST_SAMPLE_SIZE]).unwrap_err().code, 23);
+        assert_eq!(check_self_test_sample(&[0xAA; SELF_TEST_SAMPLE_SIZE]).unwrap_err().code, 23);
+        let mut long_run = [0x5A; SELF_TEST_SAMPLE_SIZE];
+        long_run[100..104].fill(0xFF);
+        assert_eq!(check_self_test_sample(&long_run).unwrap_err().code, 23);
+    }
+
+    #[test]
+    fn test_add_entropy_pool_bounded() {
+        set_entropy_override(Some([1_700_000_000_000_000_000, 42, 16, 8, 4, 1234, 3600, 1_700_000_000, 99, 8]));
+        let nebula = Nebula::new(12345);
+
+        for _ in 0..50 {
+            nebula.add_entropy().unwrap();
+            assert!(nebula.pool.lock().unwrap().len() <= MAX_POOL_SIZE, "The pool grew past MAX_POOL_SIZE");
+        }
+        set_entropy_override(None);
+
+        assert_eq!(nebula.pool.lock().unwrap().len(), MAX_POOL_SIZE);
+    }
+
+    #[test]
+    fn test_add_entropy_from_path() {
+        let path = std::env::temp_dir().join(format!("horizon_device_{}", std::process::id()));
+        std::fs::write(&path, [0x5Au8; 256]).unwrap();
+
+        let nebula = Nebula::new(12345);
+        nebula.add_entropy_from_path(&path).unwrap();
+        let first = nebula.pool.lock().unwrap().clone();
+        nebula.add_entropy_from_path(&path).unwrap();
+        let second = nebula.pool.lock().unwrap().clone();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(first.len(), 64);
+        assert_ne!(first, second, "The pool did not change");
+
+        let error = nebula.add_entropy_from_path(&path).unwrap_err();
+        assert_eq!(error.code, 2);
+        assert_eq!(*nebula.pool.lock().unwrap(), second);
+    }
+
     #[test]
     fn test_generate_random_bytes() {
         let mut rng = Nebula::new(12345);
-        let first = rng.generate_random_bytes(10);
-        let second = rng.generate_random_bytes(10);
+        let first = rng.generate_random_bytes(10).unwrap();
+        let second = rng.generate_random_bytes(10).unwrap();
         assert_ne!(first, second, "Les deux appels à generate_random_bytes ont produit les mêmes résultats");
     }
 
+    #[test]
+    fn test_repetition_count_test_stuck() {
+        // Deterministic stuck source: always returns the same byte
+        let stuck_source = std::iter::repeat(0x42u8);
+        let mut repetition_test = RepetitionCountTest::new();
+
+        let mut accepted = 0;
+        let mut error = None;
+        for byte in stuck_source.take(REPETITION_CUTOFF * 2) {
+            match repetition_test.check(byte) {
+                Ok(()) => accepted += 1,
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+        }
+
+        assert_eq!(accepted, REPETITION_CUTOFF - 1);
+        assert_eq!(error.expect("the stuck source was not detected").code, 12);
+    }
+
+    #[test]
+    fn test_repetition_count_test_healthy() {
+        let mut repetition_test = RepetitionCountTest::new();
+        for byte in (0..=255u8).cycle().take(10000) {
+            assert!(repetition_test.check(byte).is_ok());
+        }
+
+        let mut rng = Nebula::new(12345);
+        assert!(rng.generate_random_bytes(1000).is_ok());
+    }
+
     #[test]
     fn test_printer(){
         let mut rng = Nebula::new(12345);
         for _ in 0..10 {
             rng.reseed(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos());
-            let random_bytes = rng.generate_random_number();
+            let random_bytes = rng.generate_random_number().unwrap();
             println!("{:?}", random_bytes);
         }
     }
@@ -662,10 +1874,11 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_shuffle_string() {
         let mut s = "1234567890".chars().collect::<Vec<_>>();
         let original = s.clone().into_iter().collect::<String>();
-        shuffle(&mut s);
+        shuffle(&mut s).unwrap();
         let shuffled = s.into_iter().collect::<String>();
         println!("shuffled: {}", shuffled);
         assert_ne!(shuffled, original, "The string was not shuffled");
@@ -681,6 +1894,34 @@ mod tests {
         println!("shuffled: {}", shuffled);
     }
 
+    #[test]
+    fn test_unbiased_shuffle_distribution() {
+        const SHUFFLES: usize = 6000;
+
+        let mut rng = Nebula::new(12345);
+        let mut distribution_counts = HashMap::new();
+
+        for _ in 0..SHUFFLES {
+            let mut items = [0, 1, 2];
+            unbiased_shuffle(&mut items, &mut rng);
+            *distribution_counts.entry(items).or_insert(0) += 1;
+        }
+
+        // Each of the 6 permutations is expected 1000 times, the standard deviation is about 29
+        assert_eq!(distribution_counts.len(), 6, "Some permutations were never produced");
+        for (permutation, count) in &distribution_counts {
+            assert!((850..=1150).contains(count), "Permutation {:?} is not uniformly distributed", permutation);
+        }
+    }
+
+    #[test]
+    fn test_gen_index_bounds() {
+        let mut rng = Nebula::new(12345);
+        for bound in 1..100 {
+            assert!(gen_index(&mut rng, bound) < bound);
+        }
+    }
+
     #[test]
     fn test_generate_bounded_number_distribution() {
         let mut rng = Nebula::new(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos());
@@ -709,18 +1950,32 @@ mod tests {
     #[test]
     fn test_monobit() {
         let mut rng = Nebula::new(12345);
-        let sequence = rng.generate_random_bytes(1000000);
+        let sequence = rng.generate_random_bytes(1000000).unwrap();
         assert!(monobit_test(&sequence), "monobit test has not been passed");
     }
 
+    #[test]
+    fn test_write_random() {
+        // Fixed entropy and time make the output, and so the monobit result, reproducible
+        set_entropy_override(Some([1_700_000_000_000_000_000, 42, 16, 8, 4, 1234, 3600, 1_700_000_000, 99, 8]));
+        let clock = ManualClock(std::sync::Arc::new(Mutex::new(1_700_000_000_000_000_000)));
+
+        let mut sink = Vec::new();
+        Nebula::with_clock(12345, clock).write_random(&mut sink, 1 << 20).unwrap();
+        set_entropy_override(None);
+
+        assert_eq!(sink.len(), 1 << 20);
+        assert!(monobit_test(&sink), "monobit test has not been passed");
+    }
+
     #[test]
     fn test_secureseed() {
-        let a = secured_seed();
+        let a = secured_seed().unwrap();
         println!("{a}");
         let mut rng = Nebula::new(a);
 
         for _ in 0..10 {
-            let random_bytes = rng.generate_random_number();
+            let random_bytes = rng.generate_random_number().unwrap();
             println!("{:?}", random_bytes);
         }
 
@@ -757,7 +2012,46 @@ mod tests {
     #[test]
     fn test_global(){
         //println!("{}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos());
-        println!("{}",secured_seed());
+        println!("{}",secured_seed().unwrap());
+    }
+
+    #[test]
+    fn test_add_entropy_reuses_system_snapshot() {
+        struct RestoreInterval;
+
+        impl Drop for RestoreInterval {
+            fn drop(&mut self) {
+                set_entropy_refresh_interval(DEFAULT_REFRESH_INTERVAL);
+            }
+        }
+
+        let _restore = RestoreInterval;
+        set_entropy_refresh_interval(Duration::from_secs(3600));
+        data_computer().unwrap();
+        let refresh_count = SYSTEM_SNAPSHOT.lock().unwrap().as_ref().unwrap().refresh_count;
+
+        let rng = Nebula::new(12345);
+        rng.add_entropy().unwrap();
+        rng.add_entropy().unwrap();
+
+        let snapshot = SYSTEM_SNAPSHOT.lock().unwrap();
+        assert_eq!(snapshot.as_ref().unwrap().refresh_count, refresh_count, "The system snapshot was refreshed within the interval");
+    }
+
+    #[test]
+    fn test_entropy_override_reproducible() {
+        set_entropy_override(Some([1_700_000_000_000_000_000, 42, 16, 8, 4, 1234, 3600, 1_700_000_000, 99, 8]));
+
+        let mut first = Nebula::new(12345);
+        let mut second = Nebula::new(12345);
+        first.add_entropy().unwrap();
+        second.add_entropy().unwrap();
+
+        assert_eq!(*first.pool.lock().unwrap(), *second.pool.lock().unwrap(), "add_entropy is not reproducible with the override");
+        assert_eq!(first.generate_random_bytes(64).unwrap(), second.generate_random_bytes(64).unwrap());
+        assert_eq!(secured_seed().unwrap(), secured_seed().unwrap());
+
+        set_entropy_override(None);
     }
 
     #[test]
@@ -0,0 +1,197 @@
+use std::error::Error;
+
+use secrecy::Zeroize;
+
+use crate::cryptex::{decrypt_file_mode, encrypt_file_mode, CipherMode};
+use crate::gene3;
+use crate::keys::{derive_round_key, KeyMaterial, RowKey, TableKey};
+use crate::systemtrayerror::SystemTrayError;
+
+/// Encrypts content several times in a row, with every round key derived from one master key.
+///
+/// The table key is `TableKey::derive(master)` and round `i` uses `derive_round_key(master, i)` as its row key,
+/// so the master key, or the password it comes from, and the number of rounds are all that is needed to decrypt:
+/// no per-round state has to be stored next to the cipher text. Each round applies its own `CipherMode`, the full
+/// table cipher by default; like the number of rounds, a schedule given to `with_schedule` has to be given again to decrypt.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::rounds::MultiRound;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let cipher_text = MultiRound::from_password(b"master password", 6)?.encrypt(b"message")?;
+///
+/// // Later, from the stored cipher text and the password only
+/// let plain_text = MultiRound::from_password(b"master password", 6)?.decrypt(&cipher_text)?;
+/// assert_eq!(plain_text, b"message");
+/// # Ok(())
+/// # }
+/// ```
+pub struct MultiRound {
+    key1: TableKey,
+    round_keys: Vec<RowKey>,
+    schedule: Vec<CipherMode>,
+}
+
+impl MultiRound {
+    /// Derives the keys of `rounds` rounds from a master key.
+    ///
+    /// # Arguments
+    ///
+    /// * `master` - The master key.
+    /// * `rounds` - The number of rounds, which has to be the same for decryption.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SystemTrayError` code 29 if `rounds` is zero, which would leave the content unencrypted.
+    pub fn new(master: &impl KeyMaterial, rounds: u32) -> Result<Self, SystemTrayError> {
+        if rounds == 0 {
+            return Err(SystemTrayError::new(29));
+        }
+
+        Ok(MultiRound {
+            key1: TableKey::derive(master),
+            round_keys: (0..rounds).map(|round| RowKey::new(derive_round_key(master, round))).collect(),
+            schedule: vec![CipherMode::Table; rounds as usize],
+        })
+    }
+
+    /// Derives the keys of `rounds` rounds from a master key, round `i` using the mode `schedule[i]`.
+    ///
+    /// # Arguments
+    ///
+    /// * `master` - The master key.
+    /// * `rounds` - The number of rounds, which has to be the same for decryption.
+    /// * `schedule` - The mode of each round, which has to be the same for decryption. A `CipherMode::XorOnly` round
+    ///   carries its nonce, so a schedule holding one has to be built with a fresh nonce for every message.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SystemTrayError` code 29 if `rounds` is zero, and code 26 if `schedule` does not have one mode per round.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use horizon::cryptex::CipherMode;
+    /// # use horizon::gene3;
+    /// # use horizon::nebula::secured_seed;
+    /// # use horizon::rounds::MultiRound;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let master = gene3(b"schedule master")?;
+    /// let nonce = secured_seed()?.to_be_bytes();
+    /// let schedule = vec![CipherMode::Table, CipherMode::XorOnly { nonce }, CipherMode::Table];
+    /// let cipher_text = MultiRound::with_schedule(&master, 3, schedule)?.encrypt(b"message")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_schedule(master: &impl KeyMaterial, rounds: u32, schedule: Vec<CipherMode>) -> Result<Self, SystemTrayError> {
+        let multi_round = MultiRound::new(master, rounds)?;
+        if schedule.len() != rounds as usize {
+            return Err(SystemTrayError::new(26));
+        }
+
+        Ok(MultiRound { schedule, ..multi_round })
+    }
+
+    /// Returns the mode of each round.
+    pub fn schedule(&self) -> &[CipherMode] {
+        &self.schedule
+    }
+
+    /// Derives the master key from a password with `gene3`, then the keys of `rounds` rounds.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `gene3`, or `SystemTrayError` code 29 if `rounds` is zero.
+    pub fn from_password(password: &[u8], rounds: u32) -> Result<Self, SystemTrayError> {
+        MultiRound::new(&gene3(password)?, rounds)
+    }
+
+    /// Encrypts the content with `encrypt_file_mode` once per round, in round order.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the failing round.
+    pub fn encrypt(&self, plain_text: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut content = plain_text.to_vec();
+        for (key2, &mode) in self.round_keys.iter().zip(&self.schedule) {
+            let mut previous = content;
+            content = encrypt_file_mode(&previous, &self.key1, key2, mode)?;
+            previous.zeroize();
+        }
+
+        Ok(content)
+    }
+
+    /// Decrypts content produced by `encrypt` with the same master key and number of rounds, undoing the rounds in reverse.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the failing round.
+    pub fn decrypt(&self, cipher_text: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut content = cipher_text.to_vec();
+        for (key2, &mode) in self.round_keys.iter().zip(&self.schedule).rev() {
+            let mut previous = content;
+            content = decrypt_file_mode(&previous, &self.key1, key2, mode)?;
+            previous.zeroize();
+        }
+
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cryptex::XOR_NONCE_LENGTH;
+
+    use super::*;
+
+    #[test]
+    fn test_multi_round_from_master_only() {
+        let plain_text = b"ce soir je sors ne t'inquiete pas".to_vec();
+
+        let cipher_text = {
+            let rounds = MultiRound::from_password(b"LeMOTdePAsse34!", 6).unwrap();
+            rounds.encrypt(&plain_text).unwrap()
+        };
+        assert_ne!(cipher_text, plain_text);
+
+        // Nothing but the password and the round count survives
+        let decrypted = MultiRound::from_password(b"LeMOTdePAsse34!", 6).unwrap().decrypt(&cipher_text).unwrap();
+        assert_eq!(decrypted, plain_text);
+
+        assert_ne!(MultiRound::from_password(b"LeMOTdePAsse34!", 5).unwrap().decrypt(&cipher_text).ok(), Some(plain_text.clone()));
+        assert_ne!(MultiRound::from_password(b"another password", 6).unwrap().decrypt(&cipher_text).ok(), Some(plain_text));
+    }
+
+    #[test]
+    fn test_multi_round_schedule() {
+        let master = gene3(b"schedule master").unwrap();
+        let plain_text = b"table, then xor, then table again".to_vec();
+        let schedule = vec![CipherMode::Table, CipherMode::XorOnly { nonce: [9; XOR_NONCE_LENGTH] }, CipherMode::Table];
+
+        let rounds = MultiRound::with_schedule(&master, 3, schedule.clone()).unwrap();
+        assert_eq!(rounds.schedule(), schedule);
+        let cipher_text = rounds.encrypt(&plain_text).unwrap();
+
+        let decrypted = MultiRound::with_schedule(&master, 3, schedule).unwrap().decrypt(&cipher_text).unwrap();
+        assert_eq!(decrypted, plain_text);
+
+        // The default schedule is all tables, which does not undo the XOR round
+        let all_tables = MultiRound::new(&master, 3).unwrap();
+        assert_eq!(all_tables.schedule(), [CipherMode::Table; 3]);
+        assert_ne!(all_tables.decrypt(&cipher_text).unwrap(), plain_text);
+
+        let error = MultiRound::with_schedule(&master, 2, vec![CipherMode::Table; 3]).err().unwrap();
+        assert_eq!(error.code, 26);
+    }
+
+    #[test]
+    fn test_multi_round_rejects_zero_rounds() {
+        let master = gene3(b"zero rounds master").unwrap();
+
+        assert_eq!(MultiRound::new(&master, 0).err().unwrap().code, 29);
+        assert_eq!(MultiRound::with_schedule(&master, 0, Vec::new()).err().unwrap().code, 29);
+        assert_eq!(MultiRound::from_password(b"zero rounds password", 0).err().unwrap().code, 29);
+    }
+}
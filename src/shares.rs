@@ -0,0 +1,335 @@
+use std::error::Error;
+
+use secrecy::{ExposeSecret, Secret, Zeroize};
+
+use crate::cryptex::{decrypt_file_ref, encrypt_file_ref};
+use crate::kdfwagen::kdfwagen_with_length;
+use crate::keys::{KeyMaterial, RowKey, TableKey};
+use crate::{constant_time_eq, KEY_LENGTH};
+use crate::nebula::{Nebula, secured_seed};
+use crate::systemtrayerror::SystemTrayError;
+
+/// Salt of the joint key derivation of `encrypt_2of2`, the holder keys are already random.
+const JOINT_KEY_SALT: &[u8] = b"horizon 2of2 joint key";
+/// Iterations of the joint key derivation of `encrypt_2of2`.
+const JOINT_KEY_ITERATIONS: usize = 10;
+
+/// One share of a key split by `split_key`.
+pub struct KeyShare {
+    /// The point the share polynomials are evaluated at, from 1 to the number of shares.
+    pub index: u8,
+    /// The number of shares needed to rebuild the key.
+    pub threshold: u8,
+    /// The share itself, as long as the key.
+    pub value: Secret<Vec<u8>>,
+}
+
+/// Splits a key into `n` shares, any `k` of which rebuild it with `combine_shares`.
+///
+/// Each byte of the key is shared with Shamir's scheme over GF(256): it is the constant term of a random
+/// polynomial of degree `k - 1`, and share `i` holds the polynomial evaluated at `i`. Fewer than `k` shares
+/// reveal nothing about the key.
+///
+/// # Arguments
+///
+/// * `key` - The key to split.
+/// * `k` - The number of shares needed to rebuild the key.
+/// * `n` - The number of shares to create.
+///
+/// # Returns
+///
+/// The `n` shares, or `SystemTrayError` code 20 if `k` is 0 or greater than `n`.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::shares::split_key;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let master = gene3(b"my_password")?;
+/// let shares = split_key(&master, 3, 5)?;
+/// assert_eq!(shares.len(), 5);
+/// # Ok(())
+/// # }
+/// ```
+pub fn split_key(key: &Secret<Vec<u8>>, k: u8, n: u8) -> Result<Vec<KeyShare>, SystemTrayError> {
+    if k == 0 || k > n {
+        return Err(SystemTrayError::new(20));
+    }
+
+    let key = key.expose_secret();
+    let mut rng = Nebula::new(secured_seed()?);
+    let coefficients = Secret::new(rng.generate_random_bytes(key.len() * (k as usize - 1))?);
+    let coefficients = coefficients.expose_secret();
+
+    Ok((1..=n).map(|x| {
+        let value = key.iter().enumerate().map(|(byte_index, &secret)| {
+            let byte_coefficients = &coefficients[byte_index * (k as usize - 1)..(byte_index + 1) * (k as usize - 1)];
+
+            // Horner's method, from the highest degree down to the secret
+            byte_coefficients.iter().rev().chain(std::iter::once(&secret)).fold(0, |acc, &coefficient| gf_mul(acc, x) ^ coefficient)
+        }).collect();
+
+        KeyShare { index: x, threshold: k, value: Secret::new(value) }
+    }).collect())
+}
+
+/// Rebuilds a key from shares created by `split_key`.
+///
+/// # Arguments
+///
+/// * `shares` - At least `threshold` distinct shares of the same key.
+///
+/// # Returns
+///
+/// The key, rebuilt from the first `threshold` shares.
+///
+/// # Errors
+///
+/// Returns `SystemTrayError` code 20 if a share has a threshold of 0, and code 21 if there are not enough shares,
+/// if they are duplicated or inconsistent, or if a share beyond the first `threshold` does not match the key they rebuild.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::shares::{combine_shares, split_key, KeyShare};
+/// # use secrecy::ExposeSecret;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key = gene3(b"my_password")?;
+/// # let shares = split_key(&key, 3, 5)?;
+/// // Shares 1, 3 and 5
+/// let subset: Vec<KeyShare> = shares.into_iter().step_by(2).collect();
+/// let master = combine_shares(&subset)?;
+/// # assert_eq!(master.expose_secret(), key.expose_secret());
+/// # Ok(())
+/// # }
+/// ```
+pub fn combine_shares(shares: &[KeyShare]) -> Result<Secret<Vec<u8>>, SystemTrayError> {
+    let first = shares.first().ok_or_else(|| SystemTrayError::new(21))?;
+    let length = first.value.expose_secret().len();
+
+    if first.threshold == 0 {
+        return Err(SystemTrayError::new(20));
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(SystemTrayError::new(21));
+    }
+    for (i, share) in shares.iter().enumerate() {
+        if share.index == 0
+            || share.threshold != first.threshold
+            || share.value.expose_secret().len() != length
+            || shares[..i].iter().any(|other| other.index == share.index) {
+            return Err(SystemTrayError::new(21));
+        }
+    }
+
+    let (used, extra) = shares.split_at(first.threshold as usize);
+
+    // The extra shares have to lie on the polynomials the first ones define
+    for share in extra {
+        let mut expected = interpolate(used, share.index);
+        let matches = constant_time_eq(&expected, share.value.expose_secret());
+        expected.zeroize();
+        if !matches {
+            return Err(SystemTrayError::new(21));
+        }
+    }
+
+    Ok(Secret::new(interpolate(used, 0)))
+}
+
+/// Evaluates at `x` the polynomials going through `shares`, one per byte, by Lagrange interpolation.
+fn interpolate(shares: &[KeyShare], x: u8) -> Vec<u8> {
+    // Subtraction is XOR in GF(256)
+    let weights: Vec<u8> = shares.iter().map(|share| {
+        shares.iter()
+            .filter(|other| other.index != share.index)
+            .fold(1, |weight, other| gf_mul(weight, gf_mul(x ^ other.index, gf_inv(other.index ^ share.index))))
+    }).collect();
+
+    let length = shares[0].value.expose_secret().len();
+    (0..length).map(|byte_index| {
+        shares.iter().zip(&weights).fold(0, |acc, (share, &weight)| acc ^ gf_mul(share.value.expose_secret()[byte_index], weight))
+    }).collect()
+}
+
+/// Encrypts content under two keys held by different people, both needed to decrypt it.
+///
+/// Unlike `encrypt_file`, where each key has its own role and each one alone shapes part of the cipher,
+/// the keys are only used together: both are run through `kdfwagen` as one password, and the table and row keys
+/// are derived from the result. Knowing one key tells nothing about the keys actually used.
+///
+/// # Arguments
+///
+/// * `plain_text` - The content to be encrypted.
+/// * `key_a` - The key of the first holder.
+/// * `key_b` - The key of the second holder.
+///
+/// # Returns
+///
+/// A `Result` containing the encrypted content, or an error if the derivation or encryption fails.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::shares::{decrypt_2of2, encrypt_2of2};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let alice_key = gene3(b"alice password")?;
+/// # let bob_key = gene3(b"bob password")?;
+/// # let plain_text = b"released with both keys".to_vec();
+/// let cipher_text = encrypt_2of2(&plain_text, &alice_key, &bob_key)?;
+/// let decrypted = decrypt_2of2(&cipher_text, &alice_key, &bob_key)?;
+/// assert_eq!(decrypted, plain_text);
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt_2of2(plain_text: &[u8], key_a: &impl KeyMaterial, key_b: &impl KeyMaterial) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (key1, key2) = joint_keys(key_a, key_b)?;
+    encrypt_file_ref(plain_text, &key1, &key2)
+}
+
+/// Decrypts content produced by `encrypt_2of2` with both keys.
+///
+/// As with `decrypt_file`, wrong keys are not detected: they give unrelated bytes.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The content to be decrypted.
+/// * `key_a` - The key of the first holder.
+/// * `key_b` - The key of the second holder.
+///
+/// # Returns
+///
+/// A `Result` containing the decrypted content, or an error if the derivation or decryption fails.
+pub fn decrypt_2of2(cipher_text: &[u8], key_a: &impl KeyMaterial, key_b: &impl KeyMaterial) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (key1, key2) = joint_keys(key_a, key_b)?;
+    decrypt_file_ref(cipher_text, &key1, &key2)
+}
+
+/// Derives the cipher keys from both holder keys, prefixing the length of `key_a` so the boundary between them is unambiguous.
+fn joint_keys(key_a: &impl KeyMaterial, key_b: &impl KeyMaterial) -> Result<(TableKey, RowKey), SystemTrayError> {
+    let mut password = Vec::with_capacity(8 + key_a.expose().len() + key_b.expose().len());
+    password.extend_from_slice(&(key_a.expose().len() as u64).to_be_bytes());
+    password.extend_from_slice(key_a.expose());
+    password.extend_from_slice(key_b.expose());
+
+    let master = kdfwagen_with_length(&password, JOINT_KEY_SALT, JOINT_KEY_ITERATIONS, KEY_LENGTH);
+    password.zeroize();
+    let master = master?;
+
+    Ok((TableKey::derive(&master), RowKey::derive(&master)))
+}
+
+/// Multiplies two elements of GF(256) with the AES polynomial `x^8 + x^4 + x^3 + x + 1`.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Inverts a non-zero element of GF(256), as `a^254`.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+    let mut exponent = 254;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = gf_mul(result, power);
+        }
+        power = gf_mul(power, power);
+        exponent >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gene3;
+
+    use super::*;
+
+    #[test]
+    fn test_gf_inv() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_encrypt_2of2() {
+        let plain_text = b"launch codes need both officers".to_vec();
+        let key_a = gene3(b"first officer key").unwrap();
+        let key_b = gene3(b"second officer key").unwrap();
+
+        let cipher_text = encrypt_2of2(&plain_text, &key_a, &key_b).unwrap();
+        assert_eq!(decrypt_2of2(&cipher_text, &key_a, &key_b).unwrap(), plain_text);
+
+        let zero = Secret::new(vec![0u8; KEY_LENGTH]);
+        let other = gene3(b"impostor key").unwrap();
+        assert_ne!(decrypt_2of2(&cipher_text, &key_a, &zero).unwrap(), plain_text);
+        assert_ne!(decrypt_2of2(&cipher_text, &key_a, &other).unwrap(), plain_text);
+        assert_ne!(decrypt_2of2(&cipher_text, &zero, &key_b).unwrap(), plain_text);
+        assert_ne!(decrypt_2of2(&cipher_text, &key_b, &key_a).unwrap(), plain_text);
+    }
+
+    #[test]
+    fn test_split_key_3_of_5() {
+        let key = gene3(b"master key to back up").unwrap();
+        let shares = split_key(&key, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        let copy = |share: &KeyShare| KeyShare {
+            index: share.index,
+            threshold: share.threshold,
+            value: Secret::new(share.value.expose_secret().clone()),
+        };
+
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset = [copy(&shares[c]), copy(&shares[a]), copy(&shares[b])];
+                    assert_eq!(combine_shares(&subset).unwrap().expose_secret(), key.expose_secret());
+                }
+                assert!(combine_shares(&[copy(&shares[a]), copy(&shares[b])]).is_err());
+            }
+        }
+
+        assert!(combine_shares(&[copy(&shares[0]), copy(&shares[0]), copy(&shares[1])]).is_err());
+        assert!(split_key(&key, 4, 3).is_err());
+        assert!(split_key(&key, 0, 3).is_err());
+    }
+
+    #[test]
+    fn test_combine_shares_rejects_bad_shares() {
+        let key = gene3(b"master key to check").unwrap();
+        let shares = split_key(&key, 2, 4).unwrap();
+        let copy = |share: &KeyShare| KeyShare {
+            index: share.index,
+            threshold: share.threshold,
+            value: Secret::new(share.value.expose_secret().clone()),
+        };
+
+        // Every share agrees with the first two
+        let all: Vec<KeyShare> = shares.iter().map(copy).collect();
+        assert_eq!(combine_shares(&all).unwrap().expose_secret(), key.expose_secret());
+
+        let mut tampered: Vec<KeyShare> = shares.iter().map(copy).collect();
+        tampered[3].value = Secret::new(vec![0x42; KEY_LENGTH]);
+        assert_eq!(combine_shares(&tampered).err().unwrap().code, 21);
+
+        let zero_threshold = KeyShare { index: 1, threshold: 0, value: Secret::new(vec![0; KEY_LENGTH]) };
+        assert_eq!(combine_shares(&[zero_threshold]).err().unwrap().code, 20);
+    }
+}
@@ -0,0 +1,77 @@
+use std::io;
+use std::path::Path;
+
+use crate::nebula::{Nebula, secured_seed};
+
+/// Overwrites a file with random bytes, then deletes it, such as a plain text left behind after encryption.
+///
+/// The overwrite is flushed to the disk before the file is unlinked. This is best-effort: copy-on-write and
+/// journaling file systems, as well as SSD wear leveling, may keep older copies of the content.
+///
+/// # Arguments
+///
+/// * `path` - The file to wipe.
+///
+/// # Errors
+///
+/// Returns an error naming the file if it cannot be opened for writing (missing, read-only or locked),
+/// overwritten or removed, or if `secured_seed` fails. The file is left in place when the overwrite fails.
+///
+/// # Example
+///
+/// ```no_run
+/// # use horizon::cryptex::encrypt_file;
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::shred::secure_delete;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"shred key one")?);
+/// # let key2 = RowKey::new(gene3(b"shred key two")?);
+/// std::fs::write("secret.txt.enc", encrypt_file(std::fs::read("secret.txt")?, &key1, &key2)?)?;
+/// secure_delete("secret.txt")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn secure_delete(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let with_context = |action: &str, err: io::Error| {
+        io::Error::new(err.kind(), format!("cannot {action} {}: {err}", path.display()))
+    };
+
+    let seed = secured_seed().map_err(|err| with_context("overwrite", io::Error::other(err)))?;
+    overwrite_file(path, &mut Nebula::new(seed)).map_err(|err| with_context("overwrite", err))?;
+    std::fs::remove_file(path).map_err(|err| with_context("remove", err))
+}
+
+/// Replaces the whole content of a file with bytes from `nebula`, keeping its length.
+fn overwrite_file(path: &Path, nebula: &mut Nebula) -> io::Result<()> {
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let length = file.metadata()?.len();
+
+    nebula.write_random(&mut file, length as usize)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secure_delete() {
+        let path = std::env::temp_dir().join(format!("horizon_shred_{}", std::process::id()));
+        let content = b"the plain text to wipe ".repeat(500);
+        std::fs::write(&path, &content).unwrap();
+
+        overwrite_file(&path, &mut Nebula::new(12345)).unwrap();
+        let overwritten = std::fs::read(&path).unwrap();
+        assert_eq!(overwritten.len(), content.len());
+        assert_ne!(overwritten, content);
+
+        secure_delete(&path).unwrap();
+        assert!(!path.exists());
+
+        let error = secure_delete(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().contains("horizon_shred_"));
+    }
+}
@@ -25,6 +25,7 @@ impl SystemTrayError {
     /// # Examples
     ///
     /// ```rust
+    /// # use horizon::systemtrayerror::SystemTrayError;
     /// let error = SystemTrayError::new(1);
     /// println!("{:?}", error);
     /// ```
@@ -37,14 +38,55 @@ impl SystemTrayError {
             5 => "Key is too short".to_string(),
             6 => "Character not found in character set".to_string(),
             7 => "Error when dividing by 8".to_string(),
-            8 => "Error no process found".to_string(),
+            8 => "Not enough entropy sources available".to_string(),
             9 => "min is superior to max".to_string(),
             10 => "Salt is too short".to_string(),
+            11 => "Invalid archive format".to_string(),
+            12 => "Random generator is stuck repeating the same output".to_string(),
+            13 => "Requested key length exceeds the KDF maximum".to_string(),
+            14 => "Cannot serialize value".to_string(),
+            15 => "Cannot deserialize decrypted value".to_string(),
+            16 => "Invalid length in aligned cipher text".to_string(),
+            17 => "Key derivation failed".to_string(),
+            18 => "Alphabet is empty or has duplicate symbols".to_string(),
+            19 => "Derived key is weak".to_string(),
+            20 => "Share threshold must be between 1 and the number of shares".to_string(),
+            21 => "Not enough valid key shares".to_string(),
+            22 => "Encrypted output does not decrypt to the plain text".to_string(),
+            23 => "Random generator failed its self-test".to_string(),
+            24 => "Truncated log record".to_string(),
+            25 => "Unknown key derivation variant".to_string(),
+            26 => "Round schedule length does not match the number of rounds".to_string(),
+            27 => "Cannot migrate to an older format version".to_string(),
+            28 => "Plain text contains a null byte".to_string(),
+            29 => "Number of rounds must be at least 1".to_string(),
+            30 => "Cannot start the key derivation threads".to_string(),
             _ => format!("Unknown error with code {}", code),
         };
 
         SystemTrayError { message, code }
     }
+
+    /// Creates the code 10 error for a salt shorter than the minimum, telling both lengths.
+    ///
+    /// # Parameters
+    ///
+    /// - `min`: The minimum salt length in bytes.
+    /// - `got`: The length of the rejected salt.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use horizon::systemtrayerror::SystemTrayError;
+    /// let error = SystemTrayError::salt_too_short(10, 4);
+    /// assert_eq!(error.to_string(), "Salt is too short: expected at least 10 bytes, got 4");
+    /// ```
+    pub fn salt_too_short(min: usize, got: usize) -> SystemTrayError {
+        SystemTrayError {
+            message: format!("Salt is too short: expected at least {} bytes, got {}", min, got),
+            code: 10,
+        }
+    }
 }
 /// Implements the `Error` trait for the custom error type `SystemTrayError`.
 impl Error for SystemTrayError {}
@@ -63,6 +105,7 @@ impl Display for SystemTrayError {
     /// # Examples
     ///
     /// ```rust
+    /// # use horizon::systemtrayerror::SystemTrayError;
     /// let error = SystemTrayError::new(1);
     /// let formatted_message = format!("{}", error);
     /// println!("{}", formatted_message);
@@ -0,0 +1,142 @@
+use std::error::Error;
+
+use crate::cryptex::{decrypt_file, encrypt_file};
+use crate::keys::{KeyMaterial, RowKey, TableKey};
+
+/// Content that is not encrypted.
+///
+/// `encrypt` takes a `Plaintext` and `decrypt` returns one, so the compiler rejects decrypting
+/// content that was never encrypted, or encrypting a cipher text a second time by mistake,
+/// as checked by `tests/ui/text_swapped.rs`:
+///
+/// ```ignore
+/// let plain_text = Plaintext::from(b"hello".to_vec());
+///
+/// // Does not compile: the content is not a cipher text
+/// decrypt(plain_text, &key1, &key2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plaintext(pub Vec<u8>);
+
+/// Content produced by `encrypt`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ciphertext(pub Vec<u8>);
+
+impl Plaintext {
+    /// Unwraps the bytes.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Ciphertext {
+    /// Unwraps the bytes.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl From<Vec<u8>> for Plaintext {
+    fn from(bytes: Vec<u8>) -> Self {
+        Plaintext(bytes)
+    }
+}
+
+impl From<Vec<u8>> for Ciphertext {
+    fn from(bytes: Vec<u8>) -> Self {
+        Ciphertext(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Plaintext {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Ciphertext {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Encrypts a plain text with `encrypt_file`.
+///
+/// # Arguments
+///
+/// * `plain_text` - The content to be encrypted, wiped once encrypted.
+/// * `key1` - A secret key used for encryption.
+/// * `key2` - Another secret key used for encryption.
+///
+/// # Returns
+///
+/// A `Result` containing the cipher text, or an error if encryption fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::text::{encrypt, Plaintext};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"text key one")?);
+/// # let key2 = RowKey::new(gene3(b"text key two")?);
+/// let cipher_text = encrypt(Plaintext::from(std::fs::read("file.txt")?), &key1, &key2)?;
+/// std::fs::write("file.txt.enc", cipher_text.as_ref())?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt(plain_text: Plaintext, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Ciphertext, Box<dyn Error>> {
+    encrypt_file(plain_text.0, key1, key2).map(Ciphertext)
+}
+
+/// Decrypts a cipher text with `decrypt_file`.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The content to be decrypted.
+/// * `key1` - The first key used for encryption.
+/// * `key2` - The second key used for encryption.
+///
+/// # Returns
+///
+/// A `Result` containing the plain text, or an error if decryption fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::text::{decrypt, Ciphertext};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"text key one")?);
+/// # let key2 = RowKey::new(gene3(b"text key two")?);
+/// let plain_text = decrypt(Ciphertext::from(std::fs::read("file.txt.enc")?), &key1, &key2)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn decrypt(cipher_text: Ciphertext, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Plaintext, Box<dyn Error>> {
+    decrypt_file(cipher_text.0, key1, key2).map(Plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gene3;
+
+    use super::*;
+
+    #[test]
+    fn test_typed_round_trip() {
+        let key1 = TableKey::new(gene3(b"typed_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"typed_key_two").unwrap());
+        let plain_text = Plaintext::from(b"the types follow the content".to_vec());
+
+        let cipher_text: Ciphertext = encrypt(plain_text.clone(), &key1, &key2).unwrap();
+        assert_ne!(cipher_text.as_ref(), plain_text.as_ref());
+
+        // Stored bytes come back as a cipher text
+        let stored = Ciphertext::from(cipher_text.into_inner());
+        let decrypted: Plaintext = decrypt(stored, &key1, &key2).unwrap();
+        assert_eq!(decrypted, plain_text);
+    }
+}
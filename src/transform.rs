@@ -0,0 +1,268 @@
+use secrecy::{ExposeSecret, Secret};
+
+use crate::cryptex::{check_keys, CipherTables};
+use crate::keys::{KeyMaterial, RowKey, TableKey};
+use crate::systemtrayerror::SystemTrayError;
+use crate::{addition_chiffres, KEY_LENGTH, shift_bits, unshift_bits, xor_crypt3};
+
+/// A reversible, keyed transformation of a buffer.
+///
+/// The stages of the cipher are exposed as transforms so they can be composed into custom pipelines
+/// or tested on their own. Applying a sequence of transforms is undone by inverting them in reverse order.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::transform::{BitShift, Transform, XorKeystream};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = gene3(b"transform key one")?;
+/// # let key2 = gene3(b"transform key two")?;
+/// # let mut buf = b"composed stages".to_vec();
+/// let stages: Vec<Box<dyn Transform>> = vec![Box::new(XorKeystream(key1)), Box::new(BitShift(key2))];
+///
+/// stages.iter().for_each(|stage| stage.apply(&mut buf));
+/// stages.iter().rev().for_each(|stage| stage.invert(&mut buf));
+/// # assert_eq!(buf, b"composed stages");
+/// # Ok(())
+/// # }
+/// ```
+pub trait Transform {
+    /// Transforms `buf` in place.
+    fn apply(&self, buf: &mut Vec<u8>);
+
+    /// Reverses `apply` on `buf` in place.
+    fn invert(&self, buf: &mut Vec<u8>);
+}
+
+/// Rotates the bits of each byte by the matching byte of the key, like `shift_bits`.
+///
+/// # Panics
+///
+/// `apply` and `invert` panic if the key is empty and the buffer is not.
+pub struct BitShift(pub Secret<Vec<u8>>);
+
+/// XORs each byte with the matching byte of the key, like `xor_crypt3`.
+///
+/// # Panics
+///
+/// `apply` and `invert` panic if the key is empty and the buffer is not.
+pub struct XorKeystream(pub Secret<Vec<u8>>);
+
+/// Substitutes each byte through the keyed tables, like the first stage of `encrypt_file`.
+pub struct Substitution {
+    tables: CipherTables,
+    key1: Secret<Vec<u8>>,
+    key2: Secret<Vec<u8>>,
+}
+
+impl Substitution {
+    /// Creates the substitution stage for a pair of keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `key1` - The key selecting the 2D table.
+    /// * `key2` - The key selecting the row.
+    ///
+    /// # Returns
+    ///
+    /// The stage, or `SystemTrayError` code 5 if a key is shorter than `KEY_LENGTH`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use horizon::gene3;
+    /// # use horizon::keys::{RowKey, TableKey};
+    /// # use horizon::transform::Substitution;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let key1 = TableKey::new(gene3(b"transform key one")?);
+    /// # let key2 = RowKey::new(gene3(b"transform key two")?);
+    /// let substitution = Substitution::new(&key1, &key2)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Self, SystemTrayError> {
+        let key1 = key1.expose();
+        let key2 = key2.expose();
+        check_keys(key1, key2)?;
+
+        let seed = addition_chiffres(key1).wrapping_mul(addition_chiffres(key2));
+
+        Ok(Substitution {
+            tables: CipherTables::from_seed(seed),
+            key1: Secret::new(key1.to_vec()),
+            key2: Secret::new(key2.to_vec()),
+        })
+    }
+}
+
+/// An ordered list of transforms, run forward to encrypt and in reverse to decrypt.
+///
+/// Decryption is derived from the stages given for encryption, so the two directions always mirror each other.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::transform::{BitShift, Pipeline, Substitution, XorKeystream};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"transform key one")?);
+/// # let key2 = RowKey::new(gene3(b"transform key two")?);
+/// # let key1_copy = gene3(b"transform key one")?;
+/// # let shift_key = gene3(b"transform shift key")?;
+/// # let plain_text = b"through every stage".to_vec();
+/// let pipeline = Pipeline::new()
+///     .stage(Substitution::new(&key1, &key2)?)
+///     .stage(XorKeystream(key1_copy))
+///     .stage(BitShift(shift_key));
+///
+/// let cipher_text = pipeline.encrypt(plain_text);
+/// let plain_text = pipeline.decrypt(cipher_text);
+/// # assert_eq!(plain_text, b"through every stage");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Transform>>,
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline.
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// Appends a stage, run after the previous ones when encrypting.
+    pub fn stage(mut self, stage: impl Transform + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Runs the stages in order.
+    pub fn encrypt(&self, mut buf: Vec<u8>) -> Vec<u8> {
+        self.apply(&mut buf);
+        buf
+    }
+
+    /// Inverts the stages in reverse order.
+    pub fn decrypt(&self, mut buf: Vec<u8>) -> Vec<u8> {
+        self.invert(&mut buf);
+        buf
+    }
+}
+
+impl Transform for Pipeline {
+    fn apply(&self, buf: &mut Vec<u8>) {
+        self.stages.iter().for_each(|stage| stage.apply(buf));
+    }
+
+    fn invert(&self, buf: &mut Vec<u8>) {
+        self.stages.iter().rev().for_each(|stage| stage.invert(buf));
+    }
+}
+
+impl Transform for Substitution {
+    fn apply(&self, buf: &mut Vec<u8>) {
+        let key1 = self.key1.expose_secret();
+        let key2 = self.key2.expose_secret();
+        buf.iter_mut().enumerate().for_each(|(i, byte)| {
+            *byte = self.tables.encrypt_byte(key1[i % KEY_LENGTH] as usize, key2[i % KEY_LENGTH] as usize, *byte);
+        });
+    }
+
+    fn invert(&self, buf: &mut Vec<u8>) {
+        let key1 = self.key1.expose_secret();
+        let key2 = self.key2.expose_secret();
+        buf.iter_mut().enumerate().for_each(|(i, byte)| {
+            *byte = self.tables.decrypt_byte(key1[i % KEY_LENGTH] as usize, key2[i % KEY_LENGTH] as usize, *byte);
+        });
+    }
+}
+
+impl Transform for BitShift {
+    fn apply(&self, buf: &mut Vec<u8>) {
+        *buf = shift_bits(buf, Secret::new(self.0.expose_secret().clone()));
+    }
+
+    fn invert(&self, buf: &mut Vec<u8>) {
+        *buf = unshift_bits(buf, Secret::new(self.0.expose_secret().clone()));
+    }
+}
+
+impl Transform for XorKeystream {
+    fn apply(&self, buf: &mut Vec<u8>) {
+        xor_crypt3(buf, self.0.expose_secret());
+    }
+
+    fn invert(&self, buf: &mut Vec<u8>) {
+        // XOR is its own inverse
+        self.apply(buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cryptex::encrypt_file;
+    use crate::{gene3, vz_maker};
+
+    use super::*;
+
+    #[test]
+    fn test_transforms_compose() {
+        let original = b"each stage can be applied and inverted on its own".to_vec();
+        let stages: Vec<Box<dyn Transform>> = vec![
+            Box::new(XorKeystream(gene3(b"xor_stage_key").unwrap())),
+            Box::new(BitShift(gene3(b"shift_stage_key").unwrap())),
+        ];
+
+        let mut buf = original.clone();
+        stages.iter().for_each(|stage| stage.apply(&mut buf));
+        assert_ne!(buf, original);
+
+        stages.iter().rev().for_each(|stage| stage.invert(&mut buf));
+        assert_eq!(buf, original);
+    }
+
+    #[test]
+    fn test_pipeline_matches_encrypt_file() {
+        let plain_text = b"the cipher is the pipeline of its three stages".to_vec();
+        let key1 = TableKey::new(gene3(b"pipeline_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"pipeline_key_two").unwrap());
+
+        let val1 = addition_chiffres(key2.expose_secret());
+        let val2 = addition_chiffres(key1.expose_secret());
+        let pipeline = Pipeline::new()
+            .stage(Substitution::new(&key1, &key2).unwrap())
+            .stage(XorKeystream(Secret::new(key1.expose_secret().clone())))
+            .stage(BitShift(vz_maker(val1, val2, val2.wrapping_mul(val1)).unwrap()));
+
+        let cipher_text = pipeline.encrypt(plain_text.clone());
+        assert_eq!(cipher_text, encrypt_file(plain_text.clone(), &key1, &key2).unwrap());
+        assert_eq!(pipeline.decrypt(cipher_text), plain_text);
+    }
+
+    #[test]
+    fn test_random_pipelines_round_trip() {
+        let plain_text = b"any sequence of stages has to invert".repeat(20);
+        let key1 = TableKey::new(gene3(b"random_pipeline_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"random_pipeline_key_two").unwrap());
+
+        for configuration in 0..20u8 {
+            // Derive the stage list from the configuration number, up to 6 stages
+            let choices = blake3::hash(&[configuration]);
+            let mut pipeline = Pipeline::new();
+            for (i, choice) in choices.as_bytes()[..(configuration % 6 + 1) as usize].iter().enumerate() {
+                let stage_key = Secret::new(blake3::hash(&[configuration, i as u8]).as_bytes().to_vec());
+                pipeline = match choice % 3 {
+                    0 => pipeline.stage(Substitution::new(&key1, &key2).unwrap()),
+                    1 => pipeline.stage(XorKeystream(stage_key)),
+                    _ => pipeline.stage(BitShift(stage_key)),
+                };
+            }
+
+            assert_eq!(pipeline.decrypt(pipeline.encrypt(plain_text.clone())), plain_text, "Configuration {} does not round trip", configuration);
+        }
+    }
+}
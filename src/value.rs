@@ -0,0 +1,132 @@
+use std::error::Error;
+
+use secrecy::Zeroize;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::cryptex::{decrypt_file, encrypt_file};
+use crate::keys::{KeyMaterial, RowKey, TableKey};
+use crate::systemtrayerror::SystemTrayError;
+
+/// Serializes a value with `bincode` and encrypts the resulting bytes with `encrypt_file`.
+///
+/// The intermediate serialized buffer is zeroized by `encrypt_file` once it has been encrypted.
+///
+/// # Arguments
+///
+/// * `value` - The value to encrypt.
+/// * `key1` - The table key given to `encrypt_file`.
+/// * `key2` - The row key given to `encrypt_file`.
+///
+/// # Returns
+///
+/// The encrypted value, or an error if serialization (`SystemTrayError` code 14) or encryption fails.
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::value::encrypt_value;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"value key one")?);
+/// # let key2 = RowKey::new(gene3(b"value key two")?);
+/// # let settings = vec![("theme", "dark")];
+/// let cipher_text = encrypt_value(&settings, &key1, &key2)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn encrypt_value<T: Serialize>(value: &T, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let serialized = bincode::serialize(value).map_err(|_| SystemTrayError::new(14))?;
+
+    encrypt_file(serialized, key1, key2)
+}
+
+/// Decrypts bytes produced by `encrypt_value` and deserializes them back into a value.
+///
+/// The decrypted buffer is zeroized once it has been deserialized.
+///
+/// # Arguments
+///
+/// * `cipher_text` - The encrypted value.
+/// * `key1` - The table key given to `decrypt_file`.
+/// * `key2` - The row key given to `decrypt_file`.
+///
+/// # Returns
+///
+/// The decrypted value, or an error if decryption fails or the decrypted bytes are not a valid `T`
+/// (`SystemTrayError` code 15, which usually means the keys are wrong).
+///
+/// # Examples
+///
+/// ```
+/// # use horizon::gene3;
+/// # use horizon::keys::{RowKey, TableKey};
+/// # use horizon::value::{decrypt_value, encrypt_value};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let key1 = TableKey::new(gene3(b"value key one")?);
+/// # let key2 = RowKey::new(gene3(b"value key two")?);
+/// # type Settings = Vec<(String, String)>;
+/// # let cipher_text = encrypt_value(&vec![("theme", "dark")], &key1, &key2)?;
+/// let settings: Settings = decrypt_value(cipher_text, &key1, &key2)?;
+/// # assert_eq!(settings, vec![("theme".to_string(), "dark".to_string())]);
+/// # Ok(())
+/// # }
+/// ```
+pub fn decrypt_value<T: DeserializeOwned>(cipher_text: Vec<u8>, key1: &TableKey<impl KeyMaterial>, key2: &RowKey<impl KeyMaterial>) -> Result<T, Box<dyn Error>> {
+    let mut plain_text = decrypt_file(cipher_text, key1, key2)?;
+
+    let value = bincode::deserialize(&plain_text).map_err(|_| SystemTrayError::new(15));
+    plain_text.zeroize();
+
+    Ok(value?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::gene3;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Account {
+        name: String,
+        tags: Vec<String>,
+        limits: Limits,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Limits {
+        daily: u64,
+        overdraft: Option<i32>,
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let key1 = TableKey::new(gene3(b"value_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"value_key_two").unwrap());
+        let account = Account {
+            name: "Alice".to_string(),
+            tags: vec!["admin".to_string(), "ops".to_string()],
+            limits: Limits { daily: 5000, overdraft: Some(-200) },
+        };
+
+        let cipher_text = encrypt_value(&account, &key1, &key2).unwrap();
+        let decrypted: Account = decrypt_value(cipher_text, &key1, &key2).unwrap();
+
+        assert_eq!(decrypted, account);
+    }
+
+    #[test]
+    fn test_value_wrong_type() {
+        let key1 = TableKey::new(gene3(b"value_key_one").unwrap());
+        let key2 = RowKey::new(gene3(b"value_key_two").unwrap());
+
+        let cipher_text = encrypt_value(&1u8, &key1, &key2).unwrap();
+        let error = decrypt_value::<Account>(cipher_text, &key1, &key2).unwrap_err();
+
+        assert_eq!(error.downcast_ref::<SystemTrayError>().map(|err| err.code), Some(15));
+    }
+}
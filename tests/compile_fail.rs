@@ -0,0 +1,10 @@
+/// Checks that the typed wrappers turn a misuse of the API into a compile error.
+///
+/// Each `*_swapped.rs` case has a `*_in_order.rs` twin that only differs by the misuse and compiles,
+/// so the reported error can only come from the misuse.
+#[test]
+fn typed_wrappers() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/*_in_order.rs");
+    cases.compile_fail("tests/ui/*_swapped.rs");
+}
@@ -0,0 +1,10 @@
+use horizon::cryptex::encrypt_file_ref;
+use horizon::gene3;
+use horizon::keys::{RowKey, TableKey};
+
+fn main() {
+    let table_key = TableKey::new(gene3(b"first password").unwrap());
+    let row_key = RowKey::new(gene3(b"second password").unwrap());
+
+    let _ = encrypt_file_ref(b"plain text", &table_key, &row_key);
+}
@@ -0,0 +1,11 @@
+use horizon::cryptex::encrypt_file_ref;
+use horizon::gene3;
+use horizon::keys::{RowKey, TableKey};
+
+fn main() {
+    let table_key = TableKey::new(gene3(b"first password").unwrap());
+    let row_key = RowKey::new(gene3(b"second password").unwrap());
+
+    // The keys are swapped
+    let _ = encrypt_file_ref(b"plain text", &row_key, &table_key);
+}
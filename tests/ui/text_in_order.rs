@@ -0,0 +1,12 @@
+use horizon::gene3;
+use horizon::keys::{RowKey, TableKey};
+use horizon::text::{decrypt, encrypt, Plaintext};
+
+fn main() {
+    let key1 = TableKey::new(gene3(b"first password").unwrap());
+    let key2 = RowKey::new(gene3(b"second password").unwrap());
+    let plain_text = Plaintext::from(b"hello".to_vec());
+
+    let cipher_text = encrypt(plain_text.clone(), &key1, &key2).unwrap();
+    let _ = decrypt(cipher_text, &key1, &key2);
+}